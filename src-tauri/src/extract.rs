@@ -0,0 +1,740 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use fax::decoder::{decode_g4, pels};
+use fax::Color;
+use flate2::read::ZlibDecoder;
+use image::imageops::{resize, FilterType};
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageFormat, Luma, LumaA, Rgb, RgbImage, Rgba};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::error::Result;
+use crate::path::{create_dir, unique_path};
+
+/// 从 pdf 中提取的图片。
+enum Extracted {
+    /// DCTDecode 流，原样保存为 jpg
+    Jpeg(Vec<u8>),
+    /// JPXDecode 流，原样保存为 jp2
+    Jpx(Vec<u8>),
+    /// 其他编码解码后保存为 png
+    Png(DynamicImage),
+}
+
+impl Extracted {
+    fn extension(&self) -> &'static str {
+        match self {
+            Extracted::Jpeg(_) => "jpg",
+            Extracted::Jpx(_) => "jp2",
+            Extracted::Png(_) => "png",
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        match self {
+            Extracted::Jpeg(data) | Extracted::Jpx(data) => {
+                fs::write(path, data).map_err(|e| e.to_string())
+            }
+            Extracted::Png(image) => image
+                .save_with_format(path, ImageFormat::Png)
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// 图片的颜色空间。
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// 调色板，palette 中每种颜色按 base 的分量排列
+    Indexed {
+        base: Box<ColorSpace>,
+        palette: Vec<u8>,
+    },
+}
+
+impl ColorSpace {
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Indexed { .. } => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+
+    fn from_object(doc: &Document, object: &Object) -> Result<ColorSpace> {
+        let (_, object) = doc.dereference(object).map_err(|e| e.to_string())?;
+
+        if let Ok(name) = object.as_name_str() {
+            return match name {
+                "DeviceGray" | "CalGray" | "G" => Ok(ColorSpace::Gray),
+                "DeviceRGB" | "CalRGB" | "RGB" => Ok(ColorSpace::Rgb),
+                "DeviceCMYK" | "CMYK" => Ok(ColorSpace::Cmyk),
+                _ => Err(format!("不支持的颜色空间：{}", name)),
+            };
+        }
+
+        let array = object.as_array().map_err(|e| e.to_string())?;
+        let family = array
+            .first()
+            .and_then(|name| name.as_name_str().ok())
+            .unwrap_or_default();
+
+        match family {
+            "CalGray" => Ok(ColorSpace::Gray),
+            "CalRGB" => Ok(ColorSpace::Rgb),
+            // ICC 配置文件只用于确定颜色分量的数量
+            "ICCBased" => {
+                let profile = array
+                    .get(1)
+                    .ok_or("ICCBased 颜色空间缺少配置文件")
+                    .and_then(|profile| doc.dereference(profile).map_err(|_| "无效的配置文件"))
+                    .and_then(|(_, profile)| profile.as_stream().map_err(|_| "无效的配置文件"))?;
+
+                match profile.dict.get(b"N").and_then(Object::as_i64) {
+                    Ok(1) => Ok(ColorSpace::Gray),
+                    Ok(3) => Ok(ColorSpace::Rgb),
+                    Ok(4) => Ok(ColorSpace::Cmyk),
+                    _ => match profile.dict.get(b"Alternate") {
+                        Ok(alternate) => ColorSpace::from_object(doc, alternate),
+                        Err(_) => Err("无法确定 ICC 配置文件的颜色分量".to_string()),
+                    },
+                }
+            }
+            "Indexed" | "I" => {
+                let base = array.get(1).ok_or("Indexed 颜色空间缺少基础颜色空间")?;
+                let base = ColorSpace::from_object(doc, base)?;
+                if matches!(base, ColorSpace::Indexed { .. }) {
+                    return Err("调色板的基础颜色空间不能是调色板".to_string());
+                }
+
+                let lookup = array.get(3).ok_or("Indexed 颜色空间缺少调色板")?;
+                let (_, lookup) = doc.dereference(lookup).map_err(|e| e.to_string())?;
+                let palette = match lookup {
+                    Object::String(bytes, _) => bytes.clone(),
+                    Object::Stream(stream) => decode_stream(stream)?,
+                    _ => return Err("无效的调色板".to_string()),
+                };
+
+                Ok(ColorSpace::Indexed {
+                    base: Box::new(base),
+                    palette,
+                })
+            }
+            _ => Err(format!("不支持的颜色空间：{}", family)),
+        }
+    }
+
+    /// 把一个像素的分量转换为 RGB。
+    fn to_rgb(&self, components: &[u8]) -> [u8; 3] {
+        match self {
+            ColorSpace::Gray => [components[0]; 3],
+            ColorSpace::Rgb => [components[0], components[1], components[2]],
+            ColorSpace::Cmyk => {
+                let k = 255 - components[3] as u32;
+                let channel = |c: u8| ((255 - c as u32) * k / 255) as u8;
+                [
+                    channel(components[0]),
+                    channel(components[1]),
+                    channel(components[2]),
+                ]
+            }
+            ColorSpace::Indexed { base, palette } => {
+                let size = base.components();
+                let start = components[0] as usize * size;
+                match palette.get(start..start + size) {
+                    Some(color) => base.to_rgb(color),
+                    None => [0; 3],
+                }
+            }
+        }
+    }
+}
+
+/// 提取 pdf 中所有页面使用的图片，返回保存的文件。
+///
+/// 文件名为原文件名加上页码和图片在页面中的序号，如 scan_p1_1.jpg。多个页面共用的图片只提取一次。
+pub fn extract_images(input: PathBuf, output_dir: PathBuf) -> Result<Vec<PathBuf>> {
+    let doc = Document::load(&input).map_err(|e| {
+        error!("读取 pdf 文件 {:?} 时出错：{}", input, e);
+        e.to_string()
+    })?;
+
+    if doc.is_encrypted() {
+        error!("不支持加密的 pdf 文件：{:?}", input);
+        return Err("不支持加密的 pdf 文件".to_string());
+    }
+
+    create_dir(&output_dir)?;
+
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "image".to_string());
+
+    let mut visited = BTreeSet::new();
+    let mut outputs = Vec::new();
+
+    for (number, page_id) in doc.get_pages() {
+        let (resources, inherited) = doc.get_page_resources(page_id);
+
+        let mut images = Vec::new();
+        for resources in resources.into_iter().chain(
+            inherited
+                .iter()
+                .filter_map(|id| doc.get_dictionary(*id).ok()),
+        ) {
+            collect_images(&doc, resources, &mut visited, &mut images);
+        }
+
+        for (index, (id, stream)) in images.into_iter().enumerate() {
+            let extracted = match extract_image(&doc, stream) {
+                Ok(extracted) => extracted,
+                Err(e) => {
+                    warn!("跳过无法提取的图片 {:?}：{}", id, e);
+                    continue;
+                }
+            };
+
+            // 不覆盖输出目录中已有的文件
+            let output = unique_path(output_dir.join(format!(
+                "{}_p{}_{}.{}",
+                stem,
+                number,
+                index + 1,
+                extracted.extension()
+            )));
+            extracted.save(&output).map_err(|e| {
+                error!("保存图片 {:?} 时出错：{}", output, e);
+                e
+            })?;
+            debug!("已提取图片 {:?} -> {:?}", id, output);
+
+            outputs.push(output);
+        }
+    }
+
+    info!("已从 {:?} 提取 {} 张图片", input, outputs.len());
+
+    Ok(outputs)
+}
+
+/// 收集资源字典中的图片，包括表单 XObject 中嵌套的图片。
+fn collect_images<'a>(
+    doc: &'a Document,
+    resources: &'a Dictionary,
+    visited: &mut BTreeSet<ObjectId>,
+    images: &mut Vec<(ObjectId, &'a Stream)>,
+) {
+    let xobjects = match resources
+        .get(b"XObject")
+        .and_then(|xobjects| doc.dereference(xobjects))
+        .and_then(|(_, xobjects)| xobjects.as_dict())
+    {
+        Ok(xobjects) => xobjects,
+        Err(_) => return,
+    };
+
+    for (_, xobject) in xobjects.iter() {
+        let Ok(id) = xobject.as_reference() else {
+            continue;
+        };
+        if !visited.insert(id) {
+            continue;
+        }
+
+        let Ok(stream) = doc.get_object(id).and_then(Object::as_stream) else {
+            continue;
+        };
+
+        match stream.dict.get(b"Subtype").and_then(Object::as_name_str) {
+            Ok("Image") => images.push((id, stream)),
+            Ok("Form") => {
+                if let Ok((_, Object::Dictionary(resources))) = stream
+                    .dict
+                    .get(b"Resources")
+                    .and_then(|resources| doc.dereference(resources))
+                {
+                    collect_images(doc, resources, visited, images);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 提取一张图片，jpeg 和 jpeg2000 原样保存，其他编码解码为 png。
+fn extract_image(doc: &Document, stream: &Stream) -> Result<Extracted> {
+    let filters = stream.filters().unwrap_or_default();
+
+    match filters.as_slice() {
+        [filter] if filter == "DCTDecode" => return Ok(Extracted::Jpeg(stream.content.clone())),
+        [filter] if filter == "JPXDecode" => return Ok(Extracted::Jpx(stream.content.clone())),
+        _ => {}
+    }
+
+    let image = decode_image(doc, stream)?;
+
+    // 软遮罩作为透明通道，无法解码时只导出图片本身
+    let smask = stream
+        .dict
+        .get(b"SMask")
+        .and_then(Object::as_reference)
+        .and_then(|id| doc.get_object(id))
+        .and_then(Object::as_stream);
+    let image = match smask.map(|smask| decode_image(doc, smask)) {
+        Ok(Ok(mask)) => apply_smask(image, mask.to_luma8()),
+        Ok(Err(err)) => {
+            warn!("无法解码图片的软遮罩，不保留透明通道：{}", err);
+            image
+        }
+        Err(_) => image,
+    };
+
+    Ok(Extracted::Png(image))
+}
+
+/// 把软遮罩合并为图片的透明通道，遮罩尺寸不同时缩放到图片尺寸。
+fn apply_smask(image: DynamicImage, mask: GrayImage) -> DynamicImage {
+    let mask = if mask.dimensions() == (image.width(), image.height()) {
+        mask
+    } else {
+        resize(&mask, image.width(), image.height(), FilterType::Triangle)
+    };
+
+    match image {
+        DynamicImage::ImageLuma8(gray) => {
+            DynamicImage::ImageLumaA8(ImageBuffer::from_fn(gray.width(), gray.height(), |x, y| {
+                LumaA([gray.get_pixel(x, y).0[0], mask.get_pixel(x, y).0[0]])
+            }))
+        }
+        DynamicImage::ImageLuma16(_) | DynamicImage::ImageRgb16(_) => {
+            let rgb = image.to_rgb16();
+            DynamicImage::ImageRgba16(ImageBuffer::from_fn(rgb.width(), rgb.height(), |x, y| {
+                let [r, g, b] = rgb.get_pixel(x, y).0;
+                let alpha = mask.get_pixel(x, y).0[0] as u16 * 257;
+                Rgba([r, g, b, alpha])
+            }))
+        }
+        image => {
+            let rgb = image.to_rgb8();
+            DynamicImage::ImageRgba8(ImageBuffer::from_fn(rgb.width(), rgb.height(), |x, y| {
+                let [r, g, b] = rgb.get_pixel(x, y).0;
+                Rgba([r, g, b, mask.get_pixel(x, y).0[0]])
+            }))
+        }
+    }
+}
+
+/// 把图片流解码为图片，image_from 的逆过程。
+fn decode_image(doc: &Document, stream: &Stream) -> Result<DynamicImage> {
+    let dict = &stream.dict;
+    let integer = |key: &[u8]| dict.get(key).and_then(Object::as_i64).ok();
+
+    let width = integer(b"Width").ok_or("图片缺少宽度")?;
+    let height = integer(b"Height").ok_or("图片缺少高度")?;
+    let (width, height) = match (u32::try_from(width), u32::try_from(height)) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+        _ => return Err(format!("无效的图片尺寸：{}x{}", width, height)),
+    };
+
+    let image_mask = dict
+        .get(b"ImageMask")
+        .and_then(Object::as_bool)
+        .unwrap_or(false);
+
+    // 遮罩图片为 1 位灰度，0 表示绘制填充颜色
+    let (color_space, bits) = if image_mask {
+        (ColorSpace::Gray, 1)
+    } else {
+        let color_space = dict
+            .get(b"ColorSpace")
+            .map_err(|_| "图片缺少颜色空间".to_string())
+            .and_then(|color_space| ColorSpace::from_object(doc, color_space))?;
+        (
+            color_space,
+            integer(b"BitsPerComponent").unwrap_or(8) as u32,
+        )
+    };
+
+    if ![1, 2, 4, 8, 16].contains(&bits) {
+        return Err(format!("不支持的位深度：{}", bits));
+    }
+
+    // 调色板的索引最多 8 位
+    if bits > 8 && matches!(color_space, ColorSpace::Indexed { .. }) {
+        return Err(format!("不支持的调色板位深度：{}", bits));
+    }
+
+    let data = decode_stream(stream)?;
+
+    let components = color_space.components();
+    let row_bytes = (width as usize * components * bits as usize).div_ceil(8);
+    let size = row_bytes
+        .checked_mul(height as usize)
+        .ok_or(format!("图片尺寸过大：{}x{}", width, height))?;
+    if data.len() < size {
+        return Err(format!(
+            "图片数据不完整：需要 {} 字节，实际 {} 字节",
+            size,
+            data.len()
+        ));
+    }
+
+    // /Decode 为 [1 0] 时颜色反转
+    let inverted = dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|decode| decode.first())
+        .and_then(|first| first.as_float().ok())
+        .map(|first| first > 0.0)
+        .unwrap_or(false)
+        && !matches!(color_space, ColorSpace::Indexed { .. });
+
+    if bits == 16 && !matches!(color_space, ColorSpace::Indexed { .. }) {
+        return decode_16bit(&data, width, height, &color_space, inverted);
+    }
+
+    let samples = unpack_samples(&data, width, height, components, bits, row_bytes);
+    // 调色板的索引不需要缩放
+    let scale = |value: u8| -> u8 {
+        let value = match color_space {
+            ColorSpace::Indexed { .. } => value,
+            _ => ((value as u32 * 255) / ((1u32 << bits.min(8)) - 1)) as u8,
+        };
+        if inverted {
+            255 - value
+        } else {
+            value
+        }
+    };
+    let samples: Vec<u8> = samples.into_iter().map(scale).collect();
+
+    let image = match color_space {
+        ColorSpace::Gray => DynamicImage::ImageLuma8(
+            GrayImage::from_raw(width, height, samples).ok_or("图片数据不完整")?,
+        ),
+        ColorSpace::Rgb => DynamicImage::ImageRgb8(
+            RgbImage::from_raw(width, height, samples).ok_or("图片数据不完整")?,
+        ),
+        _ => {
+            let pixels: Vec<u8> = samples
+                .chunks_exact(components)
+                .flat_map(|pixel| color_space.to_rgb(pixel))
+                .collect();
+            DynamicImage::ImageRgb8(
+                RgbImage::from_raw(width, height, pixels).ok_or("图片数据不完整")?,
+            )
+        }
+    };
+
+    Ok(image)
+}
+
+/// 解码 16 位的灰度、RGB 和 CMYK 图片，CMYK 转换为 8 位 RGB。
+fn decode_16bit(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color_space: &ColorSpace,
+    inverted: bool,
+) -> Result<DynamicImage> {
+    let samples: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .map(|value| if inverted { u16::MAX - value } else { value })
+        .take(width as usize * height as usize * color_space.components())
+        .collect();
+
+    match color_space {
+        ColorSpace::Gray => ImageBuffer::<Luma<u16>, _>::from_raw(width, height, samples)
+            .map(DynamicImage::ImageLuma16),
+        ColorSpace::Rgb => ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, samples)
+            .map(DynamicImage::ImageRgb16),
+        _ => {
+            let pixels: Vec<u8> = samples
+                .chunks_exact(4)
+                .flat_map(|pixel| {
+                    let pixel: Vec<u8> = pixel.iter().map(|v| (v >> 8) as u8).collect();
+                    color_space.to_rgb(&pixel)
+                })
+                .collect();
+            RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+        }
+    }
+    .ok_or_else(|| "图片数据不完整".to_string())
+}
+
+/// 把每行按字节对齐的 1、2、4、8 位分量展开为每个分量一个字节，不缩放。
+fn unpack_samples(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    components: usize,
+    bits: u32,
+    row_bytes: usize,
+) -> Vec<u8> {
+    let per_row = width as usize * components;
+    let mut samples = Vec::with_capacity(per_row * height as usize);
+
+    for row in data.chunks(row_bytes).take(height as usize) {
+        if bits == 8 {
+            samples.extend_from_slice(&row[..per_row]);
+            continue;
+        }
+
+        let mask = (1u16 << bits) as u8 - 1;
+        let per_byte = 8 / bits as usize;
+        samples.extend((0..per_row).map(|i| {
+            let shift = 8 - bits as usize * (i % per_byte + 1);
+            (row[i / per_byte] >> shift) & mask
+        }));
+    }
+
+    samples
+}
+
+/// 按 /Filter 依次解码流的内容，不支持的编码返回错误。
+fn decode_stream(stream: &Stream) -> Result<Vec<u8>> {
+    let filters = stream.filters().unwrap_or_default();
+
+    // 每个编码对应一个解码参数，只有一个编码时可以直接使用字典
+    let params: Vec<Option<&Dictionary>> = match stream.dict.get(b"DecodeParms") {
+        Ok(Object::Array(params)) => params.iter().map(|p| p.as_dict().ok()).collect(),
+        Ok(Object::Dictionary(params)) => vec![Some(params)],
+        _ => Vec::new(),
+    };
+
+    let mut data = stream.content.clone();
+
+    for (i, filter) in filters.iter().enumerate() {
+        let params = params.get(i).copied().flatten();
+
+        data = match filter.as_str() {
+            "FlateDecode" | "Fl" => {
+                let mut output = Vec::with_capacity(data.len() * 2);
+                ZlibDecoder::new(data.as_slice())
+                    .read_to_end(&mut output)
+                    .map_err(|e| format!("解压图片数据时出错：{}", e))?;
+                undo_predictor(output, params)?
+            }
+            "CCITTFaxDecode" | "CCF" => decode_ccitt(&data, stream, params)?,
+            _ => return Err(format!("不支持的编码：{}", filter)),
+        };
+    }
+
+    Ok(data)
+}
+
+/// 还原 png 或 tiff 预测器处理过的数据。
+fn undo_predictor(data: Vec<u8>, params: Option<&Dictionary>) -> Result<Vec<u8>> {
+    let Some(params) = params else {
+        return Ok(data);
+    };
+    let integer =
+        |key: &[u8], default: i64| params.get(key).and_then(Object::as_i64).unwrap_or(default);
+
+    let predictor = integer(b"Predictor", 1);
+    let colors = integer(b"Colors", 1).max(1) as usize;
+    let bits = integer(b"BitsPerComponent", 8).max(1) as usize;
+    let columns = integer(b"Columns", 1).max(1) as usize;
+
+    // 预测器按字节处理，不足一个字节的像素按一个字节计算
+    let pixel_bytes = (colors * bits).div_ceil(8);
+    let row_bytes = (colors * bits * columns).div_ceil(8);
+
+    match predictor {
+        1 => Ok(data),
+        2 => {
+            if bits != 8 {
+                return Err(format!("不支持 {} 位的 tiff 预测器", bits));
+            }
+
+            let mut data = data;
+            for row in data.chunks_mut(row_bytes) {
+                for i in pixel_bytes..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - pixel_bytes]);
+                }
+            }
+            Ok(data)
+        }
+        10..=15 => {
+            let mut output = Vec::with_capacity(data.len());
+            let mut previous = vec![0u8; row_bytes];
+
+            // png 预测器每行前有一个字节表示这一行的过滤类型
+            for row in data.chunks(row_bytes + 1) {
+                if row.len() < row_bytes + 1 {
+                    break;
+                }
+
+                let mut current = row[1..].to_vec();
+                for i in 0..row_bytes {
+                    let left = if i >= pixel_bytes {
+                        current[i - pixel_bytes]
+                    } else {
+                        0
+                    };
+                    let up = previous[i];
+                    let up_left = if i >= pixel_bytes {
+                        previous[i - pixel_bytes]
+                    } else {
+                        0
+                    };
+
+                    current[i] = current[i].wrapping_add(match row[0] {
+                        0 => 0,
+                        1 => left,
+                        2 => up,
+                        3 => ((left as u16 + up as u16) / 2) as u8,
+                        4 => paeth(left, up, up_left),
+                        filter => return Err(format!("无效的 png 过滤类型：{}", filter)),
+                    });
+                }
+
+                output.extend_from_slice(&current);
+                previous = current;
+            }
+
+            Ok(output)
+        }
+        _ => Err(format!("不支持的预测器：{}", predictor)),
+    }
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let p = left as i16 + up as i16 - up_left as i16;
+    let (pa, pb, pc) = (
+        (p - left as i16).abs(),
+        (p - up as i16).abs(),
+        (p - up_left as i16).abs(),
+    );
+
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// 解码 CCITT G4 编码的黑白图片，输出每行按字节对齐的 1 位数据，1 表示白色。
+fn decode_ccitt(data: &[u8], stream: &Stream, params: Option<&Dictionary>) -> Result<Vec<u8>> {
+    let integer = |key: &[u8]| params.and_then(|p| p.get(key).and_then(Object::as_i64).ok());
+
+    if integer(b"K").unwrap_or(0) >= 0 {
+        return Err("只支持 CCITT G4 编码".to_string());
+    }
+
+    let width = integer(b"Columns")
+        .unwrap_or(1728)
+        .try_into()
+        .map_err(|_| "CCITT 图片宽度超出范围".to_string())?;
+    let height = stream
+        .dict
+        .get(b"Height")
+        .and_then(Object::as_i64)
+        .ok()
+        .or(integer(b"Rows"))
+        .and_then(|height| u16::try_from(height).ok());
+    let black_is_1 = params
+        .and_then(|p| p.get(b"BlackIs1").and_then(Object::as_bool).ok())
+        .unwrap_or(false);
+
+    let row_bytes = (width as usize).div_ceil(8);
+    let mut output = Vec::new();
+
+    decode_g4(data.iter().copied(), width, height, |transitions| {
+        let mut row = vec![0u8; row_bytes];
+        for (i, color) in pels(transitions, width).enumerate() {
+            if (color == Color::White) != black_is_1 {
+                row[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        output.extend(row);
+    })
+    .ok_or("解码 CCITT 图片时出错")?;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    #[test]
+    fn paeth_picks_nearest() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(50, 60, 55), 55);
+        // 距离相同时依次优先 left 和 up
+        assert_eq!(paeth(10, 10, 20), 10);
+        assert_eq!(paeth(0, 0, 0), 0);
+        assert_eq!(paeth(5, 200, 250), 5);
+    }
+
+    #[test]
+    fn undo_predictor_without_params() {
+        assert_eq!(undo_predictor(vec![1, 2, 3], None).unwrap(), vec![1, 2, 3]);
+
+        let params = dictionary! { "Predictor" => 1 };
+        assert_eq!(
+            undo_predictor(vec![1, 2, 3], Some(&params)).unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn undo_tiff_predictor() {
+        let params = dictionary! { "Predictor" => 2, "Colors" => 1, "Columns" => 4 };
+        assert_eq!(
+            undo_predictor(vec![1, 1, 1, 1, 5, 255, 2, 0], Some(&params)).unwrap(),
+            vec![1, 2, 3, 4, 5, 4, 6, 6]
+        );
+
+        let params = dictionary! { "Predictor" => 2, "Colors" => 1, "BitsPerComponent" => 4 };
+        assert!(undo_predictor(vec![0x12], Some(&params)).is_err());
+    }
+
+    #[test]
+    fn undo_png_predictor() {
+        let params = dictionary! { "Predictor" => 15, "Colors" => 1, "Columns" => 3 };
+        let data = vec![
+            0, 1, 2, 3, // None
+            1, 1, 1, 1, // Sub
+            2, 1, 1, 1, // Up
+            3, 0, 0, 0, // Average
+            4, 0, 0, 0, // Paeth
+        ];
+
+        assert_eq!(
+            undo_predictor(data, Some(&params)).unwrap(),
+            vec![1, 2, 3, 1, 2, 3, 2, 3, 4, 1, 2, 3, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn undo_png_predictor_multi_byte_pixels() {
+        let params = dictionary! { "Predictor" => 11, "Colors" => 3, "Columns" => 2 };
+
+        assert_eq!(
+            undo_predictor(vec![1, 10, 20, 200, 1, 2, 100], Some(&params)).unwrap(),
+            vec![10, 20, 200, 11, 22, 44]
+        );
+    }
+
+    #[test]
+    fn undo_predictor_invalid() {
+        let params = dictionary! { "Predictor" => 15, "Columns" => 2 };
+        assert!(undo_predictor(vec![5, 0, 0], Some(&params)).is_err());
+
+        let params = dictionary! { "Predictor" => 3 };
+        assert!(undo_predictor(vec![0], Some(&params)).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use subsetter::Profile;
+use ttf_parser::{name_id, Face};
+
+use crate::error::Result;
+
+/// Helvetica 中 ASCII 可打印字符（32～126）的宽度，单位为字号的 1/1000
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// 常见的系统中文字体
+#[cfg(target_os = "windows")]
+const CJK_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+];
+#[cfg(target_os = "macos")]
+const CJK_FONTS: &[&str] = &[
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/STHeiti Light.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/Library/Fonts/Arial Unicode.ttf",
+];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const CJK_FONTS: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/usr/share/fonts/wenquanyi/wqy-microhei/wqy-microhei.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-zenhei.ttc",
+];
+
+/// 标准 14 字体 Helvetica 是否可以绘制文字，只支持 ASCII 可打印字符。
+pub fn helvetica_supports(text: &str) -> bool {
+    text.chars().all(|c| (' '..='~').contains(&c))
+}
+
+/// 使用 Helvetica 绘制文字时的宽度，不支持的字符绘制为 `?`，按 `?` 计算宽度。
+pub fn helvetica_width(text: &str, size: f32) -> f32 {
+    let units: u32 = text
+        .chars()
+        .map(|c| {
+            let c = if helvetica_supports(&c.to_string()) {
+                c
+            } else {
+                '?'
+            };
+            let width = HELVETICA_WIDTHS.get(c as usize - 32);
+            *width.unwrap_or(&556) as u32
+        })
+        .sum();
+
+    units as f32 * size / 1000.0
+}
+
+/// Helvetica 字体对象，阅读器自带此字体，不需要嵌入。
+pub fn helvetica() -> Dictionary {
+    dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    }
+}
+
+/// 嵌入 pdf 的 TrueType/OpenType 字体，保存时只嵌入用到的字形。
+pub struct EmbeddedFont {
+    data: Vec<u8>,
+    /// 字体集合中使用的字体
+    index: u32,
+    /// 用到的字形编号及其对应的字符
+    glyphs: BTreeMap<u16, char>,
+}
+
+impl EmbeddedFont {
+    fn load(path: &Path) -> Option<EmbeddedFont> {
+        let data = fs::read(path)
+            .map_err(|err| warn!("读取字体 {:?} 时出错：{}", path, err))
+            .ok()?;
+
+        // 字体集合使用其中的第一个字体
+        if let Err(err) = Face::parse(&data, 0) {
+            warn!("解析字体 {:?} 时出错：{}", path, err);
+            return None;
+        }
+        debug!("已加载字体 {:?}", path);
+
+        Some(EmbeddedFont {
+            data,
+            index: 0,
+            glyphs: BTreeMap::new(),
+        })
+    }
+
+    /// 优先使用指定的字体文件，否则查找系统中的中文字体。
+    pub fn find(path: Option<&Path>) -> Option<EmbeddedFont> {
+        let font = path
+            .into_iter()
+            .chain(CJK_FONTS.iter().map(Path::new))
+            .filter(|path| path.exists())
+            .find_map(Self::load);
+
+        if font.is_none() {
+            warn!("没有找到可用的中文字体");
+        }
+
+        font
+    }
+
+    fn face(&self) -> Face<'_> {
+        // 加载时已检查过字体可以解析
+        Face::parse(&self.data, self.index).unwrap()
+    }
+
+    /// 绘制文字时的宽度，字体中没有的字符宽度为 0。
+    pub fn width(&self, text: &str, size: f32) -> f32 {
+        let face = self.face();
+
+        let units: u32 = text
+            .chars()
+            .filter_map(|c| face.glyph_index(c))
+            .filter_map(|glyph| face.glyph_hor_advance(glyph))
+            .map(u32::from)
+            .sum();
+
+        units as f32 * size / face.units_per_em() as f32
+    }
+
+    /// 把文字编码为 Identity-H 使用的双字节字形编号，同时记录用到的字形。
+    pub fn encode(&mut self, text: &str) -> Vec<u8> {
+        let face = Face::parse(&self.data, self.index).unwrap();
+
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            let glyph = face.glyph_index(c).map(|g| g.0).unwrap_or(0);
+            self.glyphs.entry(glyph).or_insert(c);
+            bytes.extend(glyph.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// 子集化字体并写入 pdf，font_id 是预先分配的 Type0 字体对象编号。
+    pub fn write(&self, doc: &mut Document, font_id: ObjectId) -> Result<()> {
+        let face = self.face();
+
+        let mut glyphs: Vec<u16> = self.glyphs.keys().copied().collect();
+        if glyphs.first() != Some(&0) {
+            glyphs.insert(0, 0);
+        }
+
+        // 子集化后字形编号不变
+        let subset =
+            subsetter::subset(&self.data, self.index, Profile::pdf(&glyphs)).map_err(|err| {
+                error!("子集化字体时出错：{}", err);
+                err.to_string()
+            })?;
+        debug!(
+            "字体子集包含 {} 个字形，{} 字节",
+            glyphs.len(),
+            subset.len()
+        );
+
+        let cff = face.tables().cff.is_some();
+        let to_pdf_units = |v: i16| v as i64 * 1000 / face.units_per_em() as i64;
+
+        let name = face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .unwrap_or_else(|| "Embedded".to_string());
+        let base_font = format!("{}+{}", subset_tag(&glyphs), name);
+
+        let mut file = Stream::new(dictionary! {}, subset);
+        if cff {
+            file.dict.set("Subtype", "OpenType");
+        }
+        // Ignore any compression error.
+        let _ = file.compress();
+        let file_id = doc.add_object(file);
+
+        let bbox = face.global_bounding_box();
+        let mut descriptor = dictionary! {
+            "Type" => "FontDescriptor",
+            "FontName" => Object::Name(base_font.clone().into_bytes()),
+            // 4 表示字体包含标准拉丁字符集以外的字符
+            "Flags" => 4,
+            "FontBBox" => vec![
+                to_pdf_units(bbox.x_min).into(),
+                to_pdf_units(bbox.y_min).into(),
+                to_pdf_units(bbox.x_max).into(),
+                to_pdf_units(bbox.y_max).into(),
+            ],
+            "ItalicAngle" => 0,
+            "Ascent" => to_pdf_units(face.ascender()),
+            "Descent" => to_pdf_units(face.descender()),
+            "CapHeight" => to_pdf_units(face.capital_height().unwrap_or(face.ascender())),
+            "StemV" => 80,
+        };
+        descriptor.set(if cff { "FontFile3" } else { "FontFile2" }, file_id);
+        let descriptor_id = doc.add_object(descriptor);
+
+        let mut widths = Vec::with_capacity(glyphs.len() * 2);
+        for &glyph in &glyphs {
+            let advance = face
+                .glyph_hor_advance(ttf_parser::GlyphId(glyph))
+                .unwrap_or(0);
+            widths.push(Object::Integer(glyph.into()));
+            widths.push(vec![(advance as i64 * 1000 / face.units_per_em() as i64).into()].into());
+        }
+
+        let mut cid_font = dictionary! {
+            "Type" => "Font",
+            "Subtype" => if cff { "CIDFontType0" } else { "CIDFontType2" },
+            "BaseFont" => Object::Name(base_font.clone().into_bytes()),
+            "CIDSystemInfo" => dictionary! {
+                "Registry" => Object::string_literal("Adobe"),
+                "Ordering" => Object::string_literal("Identity"),
+                "Supplement" => 0,
+            },
+            "FontDescriptor" => descriptor_id,
+            "DW" => 1000,
+            "W" => widths,
+        };
+        if !cff {
+            cid_font.set("CIDToGIDMap", "Identity");
+        }
+        let cid_font_id = doc.add_object(cid_font);
+
+        let mut to_unicode = Stream::new(dictionary! {}, self.to_unicode());
+        // Ignore any compression error.
+        let _ = to_unicode.compress();
+        let to_unicode_id = doc.add_object(to_unicode);
+
+        doc.objects.insert(
+            font_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type0",
+                "BaseFont" => Object::Name(base_font.into_bytes()),
+                "Encoding" => "Identity-H",
+                "DescendantFonts" => vec![cid_font_id.into()],
+                "ToUnicode" => to_unicode_id,
+            }),
+        );
+        debug!("已嵌入字体 {:?}", font_id);
+
+        // OpenType 字体文件需要 pdf 1.6
+        if cff && doc.version.as_str() < "1.6" {
+            doc.version = "1.6".to_string();
+        }
+
+        Ok(())
+    }
+
+    /// 字形编号到 Unicode 的映射，用于在阅读器中复制和搜索文字。
+    fn to_unicode(&self) -> Vec<u8> {
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n\
+             12 dict begin\n\
+             begincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n\
+             /CMapType 2 def\n\
+             1 begincodespacerange\n\
+             <0000> <FFFF>\n\
+             endcodespacerange\n",
+        );
+
+        let glyphs: Vec<(&u16, &char)> = self.glyphs.iter().collect();
+        // 每段最多 100 个映射
+        for chunk in glyphs.chunks(100) {
+            cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+            for (glyph, c) in chunk {
+                let unicode: String = c
+                    .encode_utf16(&mut [0; 2])
+                    .iter()
+                    .map(|u| format!("{:04X}", u))
+                    .collect();
+                cmap.push_str(&format!("<{:04X}> <{}>\n", glyph, unicode));
+            }
+            cmap.push_str("endbfchar\n");
+        }
+
+        cmap.push_str(
+            "endcmap\n\
+             CMapName currentdict /CMap defineresource pop\n\
+             end\n\
+             end\n",
+        );
+
+        cmap.into_bytes()
+    }
+}
+
+/// 子集字体名称的前缀，由 6 个大写字母组成。
+fn subset_tag(glyphs: &[u16]) -> String {
+    let mut hasher = DefaultHasher::new();
+    glyphs.hash(&mut hasher);
+    let mut hash = hasher.finish();
+
+    (0..6)
+        .map(|_| {
+            let c = (b'A' + (hash % 26) as u8) as char;
+            hash /= 26;
+            c
+        })
+        .collect()
+}
@@ -1,16 +1,30 @@
 use base64::{engine::general_purpose, Engine as _};
+use fax::decoder::{decode_g4, pels};
+use fax::encoder::Encoder;
+use fax::VecWriter;
 use image::ImageOutputFormat;
 use lazy_static::lazy_static;
 use serde::Serialize;
+use std::fs;
 use std::io::Cursor;
 use std::path::PathBuf;
 use std::process::exit;
 
+use image::codecs::{
+    gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder, tiff::TiffDecoder, webp::WebPDecoder,
+};
 use image::io::Reader as ImageReader;
-use image::{imageops::thumbnail, DynamicImage};
+use image::{
+    imageops::overlay, imageops::thumbnail, AnimationDecoder, DynamicImage, GrayImage, ImageBuffer,
+    ImageDecoder, ImageFormat, Luma, RgbaImage,
+};
+use tiff::decoder::{ifd::Value, Decoder as TiffReader, DecodingResult};
+use tiff::tags::{CompressionMethod, Tag};
+use tiff::{ColorType, TiffError, TiffResult, TiffUnsupportedError};
 
 use crate::error::Result;
 use crate::path::create_dir;
+use crate::pdf::read_pdf_preview;
 
 /// 缩略图最大宽度
 const MAX_THUMBNAIL_WIDTH: u16 = 210;
@@ -69,55 +83,840 @@ impl Into<(f32, f32)> for ImageSize {
     }
 }
 
+/// 等比缩放到能完全放入 max_size 的最大尺寸。
 pub fn scale(image_size: &ImageSize, max_size: &ImageSize) -> ImageSize {
-    let (scale_width, scale_height) = (
-        image_size.width as f32 / max_size.width as f32,
-        image_size.height as f32 / max_size.height as f32,
+    let ratio = (max_size.width as f32 / image_size.width as f32)
+        .min(max_size.height as f32 / image_size.height as f32);
+
+    scale_by(image_size, ratio)
+}
+
+/// 与 [`scale`] 相同，但不会放大比 max_size 小的图片。
+pub fn scale_down(image_size: &ImageSize, max_size: &ImageSize) -> ImageSize {
+    if image_size.width <= max_size.width && image_size.height <= max_size.height {
+        return ImageSize::from((image_size.width, image_size.height));
+    }
+
+    scale(image_size, max_size)
+}
+
+/// 等比缩放到能完全覆盖 max_size 的最小尺寸，超出的部分需要裁剪。
+pub fn scale_to_cover(image_size: &ImageSize, max_size: &ImageSize) -> ImageSize {
+    let ratio = (max_size.width as f32 / image_size.width as f32)
+        .max(max_size.height as f32 / image_size.height as f32);
+
+    scale_by(image_size, ratio)
+}
+
+fn scale_by(image_size: &ImageSize, ratio: f32) -> ImageSize {
+    ImageSize::from((
+        image_size.width as f32 * ratio,
+        image_size.height as f32 * ratio,
+    ))
+}
+
+/// EXIF 中记录的图片方向，显示时需要先水平翻转再顺时针旋转。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExifOrientation {
+    /// 是否需要水平翻转
+    pub mirror: bool,
+    /// 顺时针旋转角度
+    pub rotate: i16,
+}
+
+impl From<u32> for ExifOrientation {
+    fn from(value: u32) -> Self {
+        let (mirror, rotate) = match value {
+            2 => (true, 0),
+            3 => (false, 180),
+            4 => (true, 180),
+            5 => (true, 270),
+            6 => (false, 90),
+            7 => (true, 90),
+            8 => (false, 270),
+            _ => (false, 0),
+        };
+
+        ExifOrientation { mirror, rotate }
+    }
+}
+
+impl ExifOrientation {
+    /// 按方向变换图片像素。
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        let img = if self.mirror { img.fliph() } else { img };
+
+        match self.rotate {
+            90 => img.rotate90(),
+            180 => img.rotate180(),
+            270 => img.rotate270(),
+            _ => img,
+        }
+    }
+}
+
+/// 从 jpeg、tiff、webp 等图片的 EXIF 中读取图片方向，没有记录时返回默认方向。
+pub fn read_orientation(buffer: &[u8]) -> ExifOrientation {
+    let exif = match exif::Reader::new().read_from_container(&mut Cursor::new(buffer)) {
+        Ok(exif) => exif,
+        Err(err) => {
+            trace!("未读取到 EXIF：{}", err);
+            return ExifOrientation::default();
+        }
+    };
+
+    let orientation = exif
+        .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0));
+    debug!("EXIF 中的图片方向为 {:?}", orientation);
+
+    orientation.map(ExifOrientation::from).unwrap_or_default()
+}
+
+/// 读取 jpeg（APP2）、png（iCCP）和 tiff 中嵌入的 ICC 配置文件。
+pub fn read_icc_profile(buffer: &[u8]) -> Option<Vec<u8>> {
+    let cursor = Cursor::new(buffer);
+
+    let profile = match image::guess_format(buffer).ok()? {
+        ImageFormat::Jpeg => JpegDecoder::new(cursor).ok()?.icc_profile(),
+        ImageFormat::Png => PngDecoder::new(cursor).ok()?.icc_profile(),
+        ImageFormat::Tiff => TiffDecoder::new(cursor).ok()?.icc_profile(),
+        _ => None,
+    };
+    debug!(
+        "图片中 ICC 配置文件的大小为 {:?}",
+        profile.as_ref().map(|p| p.len())
     );
 
-    if scale_width <= 1. && scale_height <= 1. {
-        if scale_width < scale_height {
-            return ImageSize::from((
-                image_size.width as f32 * scale_width,
-                image_size.height as f32 * scale_width,
-            ));
+    profile
+}
+
+/// JPEG 2000 文件的签名框
+const JP2_SIGNATURE: &[u8] = b"\0\0\0\x0CjP  \r\n\x87\n";
+
+/// JPEG 2000 码流的 SOC 和 SIZ 标记
+const J2K_SIGNATURE: &[u8] = &[0xFF, 0x4F, 0xFF, 0x51];
+
+/// 读取 JPEG 2000（jp2 文件或 j2k 码流）的图片尺寸，不是 JPEG 2000 时返回 `None`。
+pub fn read_jpx_size(buffer: &[u8]) -> Option<ImageSize> {
+    let be_u32 = |data: &[u8], offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    // SIZ: Lsiz(2) Rsiz(2) Xsiz(4) Ysiz(4) XOsiz(4) YOsiz(4)
+    if buffer.starts_with(J2K_SIGNATURE) {
+        let siz = &buffer[4..];
+
+        return Some(ImageSize {
+            width: be_u32(siz, 4)?.checked_sub(be_u32(siz, 12)?)?,
+            height: be_u32(siz, 8)?.checked_sub(be_u32(siz, 16)?)?,
+        });
+    }
+
+    if !buffer.starts_with(JP2_SIGNATURE) {
+        return None;
+    }
+
+    // ihdr 在 jp2h 超级框中：HEIGHT(4) WIDTH(4)
+    let jp2h = jp2_box(buffer, b"jp2h")?;
+    let ihdr = jp2_box(jp2h, b"ihdr")?;
+
+    Some(ImageSize {
+        width: be_u32(ihdr, 4)?,
+        height: be_u32(ihdr, 0)?,
+    })
+}
+
+/// 查找 jp2 中指定类型的框，返回框的内容。
+fn jp2_box<'a>(buffer: &'a [u8], box_type: &[u8]) -> Option<&'a [u8]> {
+    let mut offset = 0;
+
+    while offset + 8 <= buffer.len() {
+        // LBox(4) TBox(4)，LBox 为 1 时后面是 8 字节的 XLBox，为 0 时框一直到文件结尾
+        let length = u32::from_be_bytes(buffer[offset..offset + 4].try_into().ok()?) as usize;
+        let (header, length) = match length {
+            0 => (8, buffer.len() - offset),
+            1 => (
+                16,
+                usize::try_from(u64::from_be_bytes(
+                    buffer.get(offset + 8..offset + 16)?.try_into().ok()?,
+                ))
+                .ok()?,
+            ),
+            _ => (8, length),
+        };
+
+        if length < header {
+            return None;
+        }
+
+        // XLBox 可能是任意值，相加时可能溢出
+        let end = offset.checked_add(length)?;
+
+        if &buffer[offset + 4..offset + 8] == box_type {
+            return buffer.get(offset + header..end);
         }
 
-        return ImageSize::from((
-            image_size.width as f32 * scale_height,
-            image_size.height as f32 * scale_height,
-        ));
+        offset = end;
     }
 
-    if scale_width <= 1. && scale_height > 1. {
-        return ImageSize::from((
-            image_size.width as f32 / scale_height,
-            image_size.height as f32 / scale_height,
-        ));
+    None
+}
+
+/// 未指定分辨率时使用的默认 dpi。
+pub const DEFAULT_DPI: f32 = 96.0;
+
+/// 图片分辨率，单位为 dpi。
+#[derive(Debug, Clone, Copy)]
+pub struct Dpi {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl From<f32> for Dpi {
+    fn from(value: f32) -> Self {
+        Dpi { x: value, y: value }
     }
+}
 
-    if scale_width > 1. && scale_height <= 1. {
-        return ImageSize::from((
-            image_size.width as f32 / scale_width,
-            image_size.height as f32 / scale_width,
-        ));
+impl Dpi {
+    /// 按此分辨率计算图片的物理尺寸，单位为 pdf 单元。
+    pub fn physical_size(&self, image_size: &ImageSize) -> ImageSize {
+        ImageSize::from((
+            image_size.width as f32 * 72.0 / self.x,
+            image_size.height as f32 * 72.0 / self.y,
+        ))
     }
+}
+
+/// 从图片文件中读取分辨率，目前支持 jpeg 的 JFIF 和 png 的 pHYs。
+pub fn read_dpi(buffer: &[u8]) -> Option<Dpi> {
+    let dpi = if buffer.starts_with(&[0xFF, 0xD8]) {
+        read_jfif_dpi(buffer)
+    } else if buffer.starts_with(b"\x89PNG\r\n\x1a\n") {
+        read_png_dpi(buffer)
+    } else {
+        None
+    };
+
+    // 部分图片的分辨率字段为 0
+    dpi.filter(|d| d.x > 0.0 && d.y > 0.0)
+}
+
+/// jpeg 中图像数据之前的所有段，返回段标记和段内容。
+fn jpeg_segments(buffer: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
+    let mut offset = 2;
+
+    while offset + 4 <= buffer.len() && buffer[offset] == 0xFF {
+        let marker = buffer[offset + 1];
+        let length = u16::from_be_bytes([buffer[offset + 2], buffer[offset + 3]]) as usize;
+        let segment = match buffer.get(offset + 4..offset + 2 + length) {
+            Some(segment) => segment,
+            None => break,
+        };
+
+        segments.push((marker, segment));
+
+        // SOS 之后是图像数据
+        if marker == 0xDA {
+            break;
+        }
+
+        offset += 2 + length;
+    }
+
+    segments
+}
+
+fn read_jfif_dpi(buffer: &[u8]) -> Option<Dpi> {
+    // APP0: JFIF\0 版本(2) 单位(1) X 密度(2) Y 密度(2)
+    let (_, segment) = jpeg_segments(buffer)
+        .into_iter()
+        .find(|(marker, segment)| {
+            *marker == 0xE0 && segment.len() >= 12 && segment.starts_with(b"JFIF\0")
+        })?;
+
+    let x = u16::from_be_bytes([segment[8], segment[9]]) as f32;
+    let y = u16::from_be_bytes([segment[10], segment[11]]) as f32;
+
+    match segment[7] {
+        1 => Some(Dpi { x, y }),
+        2 => Some(Dpi {
+            x: x * 2.54,
+            y: y * 2.54,
+        }),
+        // 0 表示只有宽高比，没有分辨率
+        _ => None,
+    }
+}
+
+/// jpeg 的颜色分量信息。
+#[derive(Debug, Clone, Copy)]
+pub struct JpegComponents {
+    /// 颜色分量数，1 为灰度，3 为 YCbCr/RGB，4 为 CMYK/YCCK
+    pub count: u8,
+    /// 位深
+    pub bits: u8,
+    pub width: u32,
+    pub height: u32,
+    /// 是否有 Adobe APP14 段，有此段的 CMYK jpeg 通常是反相存储的
+    pub adobe: bool,
+}
+
+/// 从 jpeg 的 SOF 段中读取颜色分量信息。
+pub fn read_jpeg_components(buffer: &[u8]) -> Option<JpegComponents> {
+    let segments = jpeg_segments(buffer);
+
+    let adobe = segments
+        .iter()
+        .any(|(marker, segment)| *marker == 0xEE && segment.starts_with(b"Adobe"));
+
+    // SOF0 ~ SOF15，不包括 DHT(C4)、JPG(C8) 和 DAC(CC)
+    // SOF: 位深(1) 高(2) 宽(2) 分量数(1)
+    segments
+        .iter()
+        .find(|(marker, segment)| {
+            (0xC0..=0xCF).contains(marker)
+                && ![0xC4, 0xC8, 0xCC].contains(marker)
+                && segment.len() >= 6
+        })
+        .map(|(_, segment)| JpegComponents {
+            count: segment[5],
+            bits: segment[0],
+            width: u16::from_be_bytes([segment[3], segment[4]]) as u32,
+            height: u16::from_be_bytes([segment[1], segment[2]]) as u32,
+            adobe,
+        })
+}
+
+/// png 中的所有块，返回块类型和块数据。
+fn png_chunks(buffer: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+
+    while offset + 8 <= buffer.len() {
+        let length = u32::from_be_bytes([
+            buffer[offset],
+            buffer[offset + 1],
+            buffer[offset + 2],
+            buffer[offset + 3],
+        ]) as usize;
+        let chunk_type = &buffer[offset + 4..offset + 8];
+        let data = match buffer.get(offset + 8..offset + 8 + length) {
+            Some(data) => data,
+            None => break,
+        };
+
+        chunks.push((chunk_type, data));
+
+        // 长度(4) 类型(4) 数据 CRC(4)
+        offset += 12 + length;
+    }
+
+    chunks
+}
+
+fn read_png_dpi(buffer: &[u8]) -> Option<Dpi> {
+    // pHYs: X 每单位像素数(4) Y 每单位像素数(4) 单位(1)，单位为 1 时表示米
+    let (_, data) = png_chunks(buffer)
+        .into_iter()
+        .take_while(|(chunk_type, _)| *chunk_type != b"IDAT")
+        .find(|(chunk_type, data)| *chunk_type == b"pHYs" && data.len() >= 9)?;
+
+    if data[8] != 1 {
+        return None;
+    }
+
+    let x = u32::from_be_bytes(data[0..4].try_into().ok()?) as f32;
+    let y = u32::from_be_bytes(data[4..8].try_into().ok()?) as f32;
+
+    Some(Dpi {
+        x: x * 0.0254,
+        y: y * 0.0254,
+    })
+}
+
+/// png 的 IHDR 块。
+#[derive(Debug, Clone, Copy)]
+pub struct PngHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    /// 0 为灰度，2 为 RGB，3 为调色板，4 为带透明通道的灰度，6 为 RGBA
+    pub color_type: u8,
+    pub interlaced: bool,
+}
+
+impl PngHeader {
+    /// 位深为 1 的灰度图片，只有黑白两种颜色。
+    pub fn is_bilevel(&self) -> bool {
+        self.color_type == 0 && self.bit_depth == 1
+    }
+}
+
+/// 从 png 的 IHDR 块中读取图片信息，不解码图像数据。
+pub fn read_png_header(buffer: &[u8]) -> Option<PngHeader> {
+    // IHDR: 宽(4) 高(4) 位深(1) 颜色类型(1) 压缩方法(1) 过滤方法(1) 隔行扫描(1)
+    let (chunk_type, ihdr) = png_chunks(buffer).into_iter().next()?;
+    if chunk_type != b"IHDR" || ihdr.len() < 13 {
+        return None;
+    }
+
+    Some(PngHeader {
+        width: u32::from_be_bytes(ihdr[0..4].try_into().ok()?),
+        height: u32::from_be_bytes(ihdr[4..8].try_into().ok()?),
+        bit_depth: ihdr[8],
+        color_type: ihdr[9],
+        interlaced: ihdr[12] != 0,
+    })
+}
 
-    if scale_width > 1. && scale_height > 1. {
-        if scale_width < scale_height {
-            return ImageSize::from((
-                image_size.width as f32 / scale_height,
-                image_size.height as f32 / scale_height,
+/// 未经解码的 png 图像数据。
+#[derive(Debug)]
+pub struct PngData {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    /// 每个像素的颜色分量数
+    pub colors: u8,
+    /// 所有 IDAT 块拼接后的 zlib 数据，每行以 png 过滤类型开头
+    pub idat: Vec<u8>,
+}
+
+/// 读取可以不经解码直接嵌入 pdf 的 png 数据。
+///
+/// 只支持非隔行扫描、位深为 8 或 16 的灰度和 RGB 图片，带透明通道的图片需要解码后分离透明通道。
+pub fn read_png_data(buffer: &[u8]) -> Option<PngData> {
+    let header = read_png_header(buffer)?;
+
+    let colors = match header.color_type {
+        0 => 1,
+        2 => 3,
+        _ => return None,
+    };
+
+    if (header.bit_depth != 8 && header.bit_depth != 16) || header.interlaced {
+        return None;
+    }
+
+    let chunks = png_chunks(buffer);
+
+    // 灰度和 RGB 图片的 tRNS 表示某种颜色透明
+    if chunks.iter().any(|(chunk_type, _)| *chunk_type == b"tRNS") {
+        return None;
+    }
+
+    let idat = chunks
+        .iter()
+        .filter(|(chunk_type, _)| *chunk_type == b"IDAT")
+        .flat_map(|(_, data)| data.iter().copied())
+        .collect();
+
+    Some(PngData {
+        width: header.width,
+        height: header.height,
+        bit_depth: header.bit_depth,
+        colors,
+        idat,
+    })
+}
+
+/// tiff 中 ICC 配置文件的标签
+const TIFF_TAG_ICC_PROFILE: u16 = 34675;
+
+/// 一帧的图像数据。
+pub enum FrameData {
+    /// 解码后的图片
+    Decoded(DynamicImage),
+    /// tiff 中的 CCITT G4 数据，不需要解码，直接嵌入 pdf
+    Fax4 {
+        data: Vec<u8>,
+        width: u32,
+        height: u32,
+        /// 为 true 时 1 表示黑色
+        black_is_1: bool,
+    },
+    /// 8 位的 CMYK 采样，image 不支持 CMYK，所以不转换为 [`DynamicImage`]
+    Cmyk {
+        data: Vec<u8>,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// 多页 tiff 的一页或动图的一帧，每帧有各自的分辨率、方向和 ICC 配置文件。
+pub struct ImageFrame {
+    pub data: FrameData,
+    pub dpi: Option<Dpi>,
+    pub orientation: ExifOrientation,
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+/// 图片的页数，只有 tiff 可能有多页，其他图片都是 1 页。
+pub fn count_frames(buffer: &[u8]) -> usize {
+    if !matches!(image::guess_format(buffer), Ok(ImageFormat::Tiff)) {
+        return 1;
+    }
+
+    let mut decoder = match TiffReader::new(Cursor::new(buffer)) {
+        Ok(decoder) => decoder,
+        Err(err) => {
+            warn!("读取 tiff 时出错：{}", err);
+            return 1;
+        }
+    };
+
+    let mut count = 1;
+    while decoder.more_images() && decoder.next_image().is_ok() {
+        count += 1;
+    }
+    debug!("tiff 共 {} 页", count);
+
+    count
+}
+
+/// 逐页解码 tiff。
+///
+/// 无法嵌入的页面记录错误后跳过，不影响其他页面，所有页面都无法嵌入时返回第一页的错误。
+pub fn read_tiff_frames(buffer: &[u8]) -> Result<Vec<ImageFrame>> {
+    let mut decoder = TiffReader::new(Cursor::new(buffer)).map_err(|err| {
+        error!("读取 tiff 时出错：{}", err);
+        err.to_string()
+    })?;
+
+    let mut frames = Vec::new();
+    let mut first_error = None;
+    let mut index = 1;
+    loop {
+        match read_tiff_frame(&mut decoder, buffer) {
+            Ok(frame) => frames.push(frame),
+            Err(err) => {
+                error!("tiff 第 {} 页无法嵌入，已跳过：{}", index, err);
+                first_error.get_or_insert(format!("tiff 第 {} 页无法嵌入：{}", index, err));
+            }
+        }
+
+        if !decoder.more_images() {
+            break;
+        }
+
+        index += 1;
+        // 页面目录损坏时无法继续读取之后的页面
+        if let Err(err) = decoder.next_image() {
+            error!("读取 tiff 第 {} 页时出错：{}", index, err);
+            first_error.get_or_insert(format!("读取 tiff 第 {} 页时出错：{}", index, err));
+            break;
+        }
+    }
+
+    match first_error {
+        Some(err) if frames.is_empty() => Err(err),
+        _ => Ok(frames),
+    }
+}
+
+/// 解码 tiff 的当前页。
+fn read_tiff_frame(decoder: &mut TiffReader<Cursor<&[u8]>>, buffer: &[u8]) -> Result<ImageFrame> {
+    let (width, height) = decoder.dimensions().map_err(|err| err.to_string())?;
+    let color_type = decoder.colortype().map_err(|err| err.to_string())?;
+    let compression = decoder
+        .find_tag_unsigned::<u16>(Tag::Compression)
+        .map_err(|err| err.to_string())?;
+    debug!(
+        "tiff 页面尺寸 {}x{}，色彩类型 {:?}，压缩方式 {:?}",
+        width, height, color_type, compression
+    );
+
+    let data = if compression == Some(CompressionMethod::Fax4.to_u16()) {
+        read_tiff_fax4(decoder, buffer, width, height)?
+    } else {
+        decode_tiff_image(decoder, width, height, color_type).map_err(|err| err.to_string())?
+    };
+
+    Ok(ImageFrame {
+        data,
+        dpi: read_tiff_dpi(decoder),
+        orientation: decoder
+            .find_tag_unsigned::<u32>(Tag::Orientation)
+            .ok()
+            .flatten()
+            .map(ExifOrientation::from)
+            .unwrap_or_default(),
+        icc_profile: decoder
+            .get_tag_u8_vec(Tag::Unknown(TIFF_TAG_ICC_PROFILE))
+            .ok(),
+    })
+}
+
+/// 解码 tiff 当前页的图像数据。
+fn decode_tiff_image(
+    decoder: &mut TiffReader<Cursor<&[u8]>>,
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+) -> TiffResult<FrameData> {
+    let image = match (color_type, decoder.read_image()?) {
+        // 黑白图片每行按字节对齐，1 表示白色
+        (ColorType::Gray(1), DecodingResult::U8(data)) => {
+            let row_bytes = (width as usize).div_ceil(8);
+            Some(DynamicImage::ImageLuma8(GrayImage::from_fn(
+                width,
+                height,
+                |x, y| {
+                    let byte = data[y as usize * row_bytes + x as usize / 8];
+                    Luma([if byte & (0x80 >> (x % 8)) == 0 {
+                        0
+                    } else {
+                        255
+                    }])
+                },
+            )))
+        }
+        (ColorType::CMYK(8), DecodingResult::U8(data))
+            if data.len() == width as usize * height as usize * 4 =>
+        {
+            return Ok(FrameData::Cmyk {
+                data,
+                width,
+                height,
+            });
+        }
+        (ColorType::Gray(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        }
+        (ColorType::GrayA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA16)
+        }
+        (ColorType::RGB(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        }
+        (ColorType::RGB(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+        }
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+        }
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+        }
+        _ => None,
+    }
+    .ok_or(TiffError::UnsupportedError(
+        TiffUnsupportedError::UnsupportedColorType(color_type),
+    ))?;
+
+    Ok(FrameData::Decoded(image))
+}
+
+/// 读取 tiff 当前页的 CCITT G4 数据。
+///
+/// 只有一个条带时直接使用原始数据，多个条带各自独立编码，需要解码后重新编码为一个数据流。
+fn read_tiff_fax4(
+    decoder: &mut TiffReader<Cursor<&[u8]>>,
+    buffer: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<FrameData> {
+    let tag_u64_vec = |decoder: &mut TiffReader<Cursor<&[u8]>>, tag| {
+        decoder
+            .get_tag_u64_vec(tag)
+            .map_err(|err| format!("读取 {:?} 时出错：{}", tag, err))
+    };
+
+    if decoder.find_tag(Tag::TileOffsets).ok().flatten().is_some() {
+        return Err("不支持分块存储的 CCITT G4 tiff".to_string());
+    }
+
+    let columns =
+        u16::try_from(width).map_err(|_| format!("CCITT G4 图片宽度 {} 超出范围", width))?;
+    let rows =
+        u16::try_from(height).map_err(|_| format!("CCITT G4 图片高度 {} 超出范围", height))?;
+
+    let offsets = tag_u64_vec(decoder, Tag::StripOffsets)?;
+    let lengths = tag_u64_vec(decoder, Tag::StripByteCounts)?;
+    let strips = offsets
+        .iter()
+        .zip(&lengths)
+        .map(|(&offset, &length)| {
+            let start = usize::try_from(offset).ok()?;
+            let end = start.checked_add(usize::try_from(length).ok()?)?;
+            buffer.get(start..end)
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|strips| !strips.is_empty() && offsets.len() == lengths.len())
+        .ok_or("CCITT G4 条带的位置超出文件范围")?;
+
+    // 0 表示 WhiteIsZero，1 表示 BlackIsZero，G4 编码中的白色对应 0
+    let black_is_1 = decoder
+        .find_tag_unsigned::<u16>(Tag::PhotometricInterpretation)
+        .ok()
+        .flatten()
+        == Some(1);
+    // 2 表示每个字节的低位在前，pdf 只支持高位在前
+    let lsb_first = decoder
+        .find_tag_unsigned::<u16>(Tag::FillOrder)
+        .ok()
+        .flatten()
+        == Some(2);
+    debug!(
+        "tiff CCITT G4 数据共 {} 个条带，BlackIs1 {}，低位在前 {}",
+        strips.len(),
+        black_is_1,
+        lsb_first
+    );
+
+    let data = if strips.len() == 1 && !lsb_first {
+        strips[0].to_vec()
+    } else {
+        let rows_per_strip = decoder
+            .find_tag_unsigned::<u32>(Tag::RowsPerStrip)
+            .ok()
+            .flatten()
+            .unwrap_or(height)
+            .max(1);
+
+        let mut encoder = Encoder::new(VecWriter::new());
+        let mut decoded_rows = 0;
+        let mut encoded = true;
+
+        for (index, strip) in strips.iter().enumerate() {
+            let strip_rows = height
+                .saturating_sub(index as u32 * rows_per_strip)
+                .min(rows_per_strip) as u16;
+            let bytes = strip
+                .iter()
+                .map(|&byte| if lsb_first { byte.reverse_bits() } else { byte });
+
+            decode_g4(bytes, columns, Some(strip_rows), |transitions| {
+                encoded &= encoder
+                    .encode_line(pels(transitions, columns), columns)
+                    .is_ok();
+                decoded_rows += 1;
+            })
+            .ok_or(format!("解码第 {} 个 CCITT G4 条带时出错", index + 1))?;
+        }
+
+        if !encoded || decoded_rows != rows {
+            return Err(format!(
+                "CCITT G4 数据共 {} 行，与图片高度 {} 不一致",
+                decoded_rows, height
             ));
         }
 
-        return ImageSize::from((
-            image_size.width as f32 / scale_width,
-            image_size.height as f32 / scale_width,
-        ));
+        encoder
+            .finish()
+            .map_err(|_| "重新编码 CCITT G4 数据时出错".to_string())?
+            .finish()
+    };
+
+    Ok(FrameData::Fax4 {
+        data,
+        width,
+        height,
+        black_is_1,
+    })
+}
+
+/// 读取 tiff 当前页的 XResolution 和 YResolution。
+fn read_tiff_dpi(decoder: &mut TiffReader<Cursor<&[u8]>>) -> Option<Dpi> {
+    let mut resolution = |tag| match decoder.find_tag(tag) {
+        Ok(Some(Value::Rational(n, d))) if d > 0 => Some(n as f32 / d as f32),
+        _ => None,
+    };
+
+    let x = resolution(Tag::XResolution)?;
+    let y = resolution(Tag::YResolution).unwrap_or(x);
+
+    // 1 表示没有单位，3 表示每厘米的像素数，默认是每英寸的像素数
+    let dpi = match decoder.find_tag_unsigned::<u16>(Tag::ResolutionUnit) {
+        Ok(Some(1)) => return None,
+        Ok(Some(3)) => Dpi {
+            x: x * 2.54,
+            y: y * 2.54,
+        },
+        _ => Dpi { x, y },
+    };
+
+    Some(dpi).filter(|d| d.x > 0.0 && d.y > 0.0)
+}
+
+/// 拼图中帧之间的间距，单位为像素
+const CONTACT_SHEET_GAP: u32 = 8;
+
+/// 解码动图（gif、webp、apng）的所有帧，不是动图时返回 `None`。
+///
+/// 每一帧都已与之前的帧合成，尺寸与画布相同。
+pub fn read_animation_frames(buffer: &[u8]) -> Result<Option<Vec<RgbaImage>>> {
+    let frames = match image::guess_format(buffer) {
+        Ok(ImageFormat::Gif) => GifDecoder::new(Cursor::new(buffer))
+            .map(|decoder| decoder.into_frames().collect_frames()),
+        Ok(ImageFormat::Png) => PngDecoder::new(Cursor::new(buffer)).map(|decoder| {
+            if !decoder.is_apng() {
+                return Ok(Vec::new());
+            }
+            decoder.apng().into_frames().collect_frames()
+        }),
+        Ok(ImageFormat::WebP) => WebPDecoder::new(Cursor::new(buffer)).map(|decoder| {
+            if !decoder.has_animation() {
+                return Ok(Vec::new());
+            }
+            decoder.into_frames().collect_frames()
+        }),
+        _ => return Ok(None),
+    }
+    .and_then(|frames| frames)
+    .map_err(|err| {
+        error!("解码动图时出错：{}", err);
+        err.to_string()
+    })?;
+
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        frames
+            .into_iter()
+            .map(|frame| frame.into_buffer())
+            .collect(),
+    ))
+}
+
+/// 把所有帧按接近正方形的网格排列成一张图片，帧之间留有透明间距。
+pub fn contact_sheet(frames: &[RgbaImage]) -> RgbaImage {
+    let (width, height) = frames.iter().fold((1, 1), |(w, h), frame| {
+        (w.max(frame.width()), h.max(frame.height()))
+    });
+
+    let columns = (frames.len() as f32).sqrt().ceil() as u32;
+    let rows = (frames.len() as u32).div_ceil(columns);
+    debug!("拼图 {} 列 {} 行，每格 {}x{}", columns, rows, width, height);
+
+    let mut sheet = RgbaImage::new(
+        columns * width + (columns - 1) * CONTACT_SHEET_GAP,
+        rows * height + (rows - 1) * CONTACT_SHEET_GAP,
+    );
+
+    for (i, frame) in frames.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        overlay(
+            &mut sheet,
+            frame,
+            (column * (width + CONTACT_SHEET_GAP)) as i64,
+            (row * (height + CONTACT_SHEET_GAP)) as i64,
+        );
     }
 
-    ImageSize::from((image_size.width, image_size.height))
+    sheet
 }
 
 #[derive(Debug, Serialize)]
@@ -125,13 +924,15 @@ pub struct Thumbnail {
     pub src: PathBuf,
     pub base64: String,
     pub name: String,
+    /// 图片的页数，多页 tiff 会生成多个 pdf 页面
+    pub frames: usize,
 }
 
 impl Thumbnail {
     pub async fn new(image_path: PathBuf) -> Result<Thumbnail> {
         trace!("创建缩略图：{:?}", image_path);
 
-        let b = Self::new_from_path(&image_path)
+        let (b, frames) = Self::new_from_path(&image_path)
             .await
             .map_err(|err| err.to_string())?;
 
@@ -144,6 +945,7 @@ impl Thumbnail {
                 .to_str()
                 .unwrap()
                 .to_string(),
+            frames,
         })
     }
 
@@ -160,17 +962,65 @@ impl Thumbnail {
         )
     }
 
-    async fn new_from_path(image_path: &PathBuf) -> Result<String> {
-        let reader = ImageReader::open(image_path).map_err(|err| {
+    /// 与原图宽高比相同的灰色占位图。
+    fn placeholder(size: &ImageSize) -> DynamicImage {
+        let scaled_size = scale(
+            size,
+            &ImageSize::from((MAX_THUMBNAIL_WIDTH as u32, MAX_THUMBNAIL_HEIGHT as u32)),
+        );
+
+        DynamicImage::ImageLuma8(GrayImage::from_pixel(
+            scaled_size.width.max(1),
+            scaled_size.height.max(1),
+            Luma([220]),
+        ))
+    }
+
+    /// 返回缩略图和图片的页数。
+    async fn new_from_path(image_path: &PathBuf) -> Result<(String, usize)> {
+        let buffer = fs::read(image_path).map_err(|err| {
             error!("读取图片时出错：{}", err);
             err.to_string()
         })?;
+
+        // pdf 文件使用第一页中的 jpeg 图片，没有时使用与第一页宽高比相同的占位图
+        if buffer.starts_with(b"%PDF") {
+            let (image, page_size, pages) = read_pdf_preview(&buffer)?;
+            let image = image.unwrap_or_else(|| {
+                debug!("pdf 文件 {:?} 使用占位缩略图", image_path);
+                Self::placeholder(&page_size)
+            });
+
+            return Ok((Self::new_from_image(image_path, &image).await?, pages));
+        }
+
+        // JPEG 2000 无法解码，使用与其宽高比相同的灰色占位图
+        if let Some(size) = read_jpx_size(&buffer) {
+            debug!("JPEG 2000 图片 {:?} 使用占位缩略图", image_path);
+
+            return Ok((
+                Self::new_from_image(image_path, &Self::placeholder(&size)).await?,
+                1,
+            ));
+        }
+        let reader = ImageReader::new(Cursor::new(&buffer))
+            .with_guessed_format()
+            .map_err(|err| {
+                error!("识别图片格式时出错：{}", err);
+                err.to_string()
+            })?;
         let img = reader.decode().map_err(|err| {
             error!("图片解码时出错：{}", err);
             err.to_string()
         })?;
 
-        Self::new_from_image(image_path, &img).await
+        // 缩略图直接旋转像素
+        let img = read_orientation(&buffer).apply(img);
+
+        Ok((
+            Self::new_from_image(image_path, &img).await?,
+            count_frames(&buffer),
+        ))
     }
 
     async fn new_from_image(image_path: &PathBuf, img: &DynamicImage) -> Result<String> {
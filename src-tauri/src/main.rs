@@ -5,8 +5,11 @@
 
 mod config;
 mod error;
+mod extract;
+mod font;
 mod image;
 mod logger;
+mod metadata;
 mod models;
 mod path;
 mod pdf;
@@ -19,17 +22,65 @@ use std::fs;
 use std::{fs::File, path::PathBuf};
 
 use crate::error::Result;
+use crate::extract::extract_images;
 use crate::image::Thumbnail;
 use crate::logger::{log_level, logger_config};
-use crate::pdf::embedd_images_to_new_pdf;
+use crate::pdf::{
+    delete_pages, embedd_images_to_existing_pdf, embedd_images_to_new_pdf, extract_pages,
+    reorder_pages, split_pdf,
+};
 use simplelog::{ColorChoice, CombinedLogger, TermLogger, TerminalMode, WriteLogger};
 
 // use tauri::Manager;
 // use window_shadows::set_shadow;
 
 #[tauri::command]
-async fn merge_images_to_pdf(output: PathBuf, images: Vec<models::Image>) -> Result<()> {
-    embedd_images_to_new_pdf(output, images).await
+async fn merge_images_to_pdf(
+    output: PathBuf,
+    images: Vec<models::Image>,
+    options: models::MergeOptions,
+) -> Result<()> {
+    embedd_images_to_new_pdf(output, images, options).await
+}
+
+#[tauri::command]
+async fn insert_images_to_pdf(
+    input: PathBuf,
+    output: PathBuf,
+    images: Vec<models::Image>,
+    options: models::MergeOptions,
+    index: Option<usize>,
+) -> Result<()> {
+    embedd_images_to_existing_pdf(input, output, images, options, index).await
+}
+
+#[tauri::command]
+async fn split_pdf_pages(
+    input: PathBuf,
+    output_dir: PathBuf,
+    ranges: Option<String>,
+) -> Result<Vec<PathBuf>> {
+    split_pdf(input, output_dir, ranges)
+}
+
+#[tauri::command]
+async fn extract_pdf_pages(input: PathBuf, output: PathBuf, ranges: String) -> Result<()> {
+    extract_pages(input, output, ranges)
+}
+
+#[tauri::command]
+async fn delete_pdf_pages(input: PathBuf, output: PathBuf, ranges: String) -> Result<()> {
+    delete_pages(input, output, ranges)
+}
+
+#[tauri::command]
+async fn reorder_pdf_pages(input: PathBuf, output: PathBuf, order: Vec<usize>) -> Result<()> {
+    reorder_pages(input, output, order)
+}
+
+#[tauri::command]
+async fn extract_pdf_images(input: PathBuf, output_dir: PathBuf) -> Result<Vec<PathBuf>> {
+    extract_images(input, output_dir)
 }
 
 #[tauri::command]
@@ -116,6 +167,12 @@ async fn main() {
         // })
         .invoke_handler(tauri::generate_handler![
             merge_images_to_pdf,
+            insert_images_to_pdf,
+            split_pdf_pages,
+            extract_pdf_pages,
+            delete_pdf_pages,
+            reorder_pdf_pages,
+            extract_pdf_images,
             generate_thumbnails
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,168 @@
+use lopdf::{dictionary, Dictionary, Object, Stream, StringFormat};
+use serde::Deserialize;
+use time::macros::format_description;
+#[cfg(target_os = "linux")]
+use time::macros::offset;
+use time::OffsetDateTime;
+
+/// 创建 pdf 的程序
+const CREATOR: &str = "Old Driver";
+/// 生成 pdf 的程序
+const PRODUCER: &str = "Old Driver (lopdf)";
+
+/// 文档信息，写入 /Info 字典和 XMP 元数据。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    /// 多个关键词之间用逗号分隔
+    pub keywords: Option<String>,
+}
+
+/// 当前时间。
+pub fn now() -> OffsetDateTime {
+    // time::OffsetDateTime::now_local() 方法在 POSIX 上线程不安全，在 linux 上直接指定时区为 +8。
+    #[cfg(target_os = "linux")]
+    let now = OffsetDateTime::now_utc().to_offset(offset!(+8));
+
+    #[cfg(not(target_os = "linux"))]
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+
+    now
+}
+
+/// pdf 日期格式，如 D:20230311145507+08'00'。
+pub fn pdf_date(date: &OffsetDateTime) -> String {
+    date.format(format_description!(
+        "D:[year][month][day][hour][minute][second][offset_hour sign:mandatory]'[offset_minute]'"
+    ))
+    .unwrap_or_default()
+}
+
+/// XMP 日期格式，如 2023-03-11T14:55:07+08:00。
+fn xmp_date(date: &OffsetDateTime) -> String {
+    date.format(format_description!(
+        "[year]-[month]-[day]T[hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
+    ))
+    .unwrap_or_default()
+}
+
+/// pdf 文本字符串，非 ASCII 文字使用带 BOM 的 UTF-16BE 编码。
+pub fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in text.encode_utf16() {
+        bytes.extend(unit.to_be_bytes());
+    }
+
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+/// 转义 XML 中的特殊字符。
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+impl Metadata {
+    /// 文档的 /Info 字典。
+    pub fn info(&self, created: &OffsetDateTime, modified: &OffsetDateTime) -> Dictionary {
+        let mut info = dictionary! {
+            "Creator" => text_string(CREATOR),
+            "Producer" => text_string(PRODUCER),
+            "CreationDate" => Object::string_literal(pdf_date(created)),
+            "ModDate" => Object::string_literal(pdf_date(modified)),
+        };
+
+        for (key, value) in [
+            ("Title", &self.title),
+            ("Author", &self.author),
+            ("Subject", &self.subject),
+            ("Keywords", &self.keywords),
+        ] {
+            if let Some(value) = value {
+                info.set(key, text_string(value));
+            }
+        }
+
+        info
+    }
+
+    /// 与 /Info 字典内容一致的 XMP 元数据流，不压缩以便文档管理系统直接读取。
+    pub fn xmp(&self, created: &OffsetDateTime, modified: &OffsetDateTime) -> Stream {
+        let mut fields = vec!["<dc:format>application/pdf</dc:format>".to_string()];
+
+        if let Some(title) = &self.title {
+            fields.push(format!(
+                "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+                escape_xml(title)
+            ));
+        }
+        if let Some(author) = &self.author {
+            fields.push(format!(
+                "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+                escape_xml(author)
+            ));
+        }
+        if let Some(subject) = &self.subject {
+            fields.push(format!(
+                "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>",
+                escape_xml(subject)
+            ));
+        }
+        if let Some(keywords) = &self.keywords {
+            fields.push(format!(
+                "<pdf:Keywords>{}</pdf:Keywords>",
+                escape_xml(keywords)
+            ));
+        }
+
+        fields.push(format!("<pdf:Producer>{}</pdf:Producer>", PRODUCER));
+        fields.push(format!("<xmp:CreatorTool>{}</xmp:CreatorTool>", CREATOR));
+        fields.push(format!(
+            "<xmp:CreateDate>{}</xmp:CreateDate>",
+            xmp_date(created)
+        ));
+        fields.push(format!(
+            "<xmp:ModifyDate>{}</xmp:ModifyDate>",
+            xmp_date(modified)
+        ));
+        fields.push(format!(
+            "<xmp:MetadataDate>{}</xmp:MetadataDate>",
+            xmp_date(modified)
+        ));
+
+        let xmp = format!(
+            "<?xpacket begin=\"\u{FEFF}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             <rdf:Description rdf:about=\"\" \
+             xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+             xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" \
+             xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\">\n\
+             {}\n\
+             </rdf:Description>\n\
+             </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>",
+            fields.join("\n")
+        );
+
+        Stream::new(
+            dictionary! {
+                "Type" => "Metadata",
+                "Subtype" => "XML",
+            },
+            xmp.into_bytes(),
+        )
+        .with_compression(false)
+    }
+}
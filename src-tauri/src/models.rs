@@ -2,15 +2,66 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::metadata::Metadata;
+use crate::pdf::{
+    Alignment, AnimationMode, Captions, FitMode, Layout, Margins, Orientation, OutlineMode,
+    PageLabel, PageText, PageType, Watermark,
+};
+
 #[derive(Serialize, Deserialize, Debug)]
 /// 要处理的图片。
 pub struct Image {
     /// 本地路径
     pub path: PathBuf,
-    // /// 旋转角度
-    // rotate: i8,
-    // /// 指定尺寸
-    // size: (f32,f32),
-    // /// 缩放比例。<scale>%
-    // scale: i8
+    /// 顺时针旋转角度，必需是 90 的倍数
+    #[serde(default)]
+    pub rotate: i16,
+    /// 指定尺寸，单位为 pdf 单元，优先于缩放比例
+    pub size: Option<(f32, f32)>,
+    /// 缩放比例。<scale>%，相对于按缩放方式计算出的尺寸
+    pub scale: Option<f32>,
+    /// 图片左下角在页面中的坐标，单位为 pdf 单元，优先于对齐方式
+    pub position: Option<(f32, f32)>,
+    /// 图片下方的说明文字，优先于文件名
+    pub caption: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+/// 合并图片时的选项。
+pub struct MergeOptions {
+    /// 页面类型
+    pub page_type: PageType,
+    /// 页面方向
+    pub orientation: Orientation,
+    /// 页边距
+    pub margins: Margins,
+    /// 图片在页边距内的对齐方式
+    pub alignment: Alignment,
+    /// 图片缩放方式
+    pub fit_mode: FitMode,
+    /// 每页的网格布局，默认每页一张图片
+    pub layout: Layout,
+    /// 按实际尺寸放置图片或页面与图片尺寸相同时使用的分辨率，为空时使用图片中记录的分辨率
+    pub dpi: Option<f32>,
+    /// 透明图片的背景色，为空时保留图片的透明通道
+    pub background: Option<[u8; 3]>,
+    /// 黑白图片是否使用 CCITT G4 编码
+    pub ccitt: bool,
+    /// 动图的处理方式
+    pub animation: AnimationMode,
+    /// 图片下方的说明文字
+    pub captions: Captions,
+    /// 页眉
+    pub header: Option<PageText>,
+    /// 页脚
+    pub footer: Option<PageText>,
+    /// 水印
+    pub watermark: Option<Watermark>,
+    /// 文档信息
+    pub metadata: Metadata,
+    /// 书签的生成方式
+    pub outline: OutlineMode,
+    /// 页码标签，阅读器中显示的页码
+    pub page_labels: Vec<PageLabel>,
 }
@@ -1,14 +1,17 @@
 use crate::error::Result;
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 pub fn create_dir<P: AsRef<Path>>(dir: P) -> Result<()> {
     let dir_ref: &Path = dir.as_ref();
 
-    if !dir_ref.exists() {
+    if dir_ref.exists() {
         return Ok(());
     }
 
-    match fs::create_dir(&dir_ref) {
+    match fs::create_dir_all(dir_ref) {
         Ok(()) => {
             debug!("已创建目录：{:?}", dir_ref);
             return Ok(());
@@ -19,3 +22,33 @@ pub fn create_dir<P: AsRef<Path>>(dir: P) -> Result<()> {
         }
     };
 }
+
+/// 根据扩展名判断是否为 pdf 文件。
+pub fn is_pdf<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("pdf"))
+        .unwrap_or(false)
+}
+
+/// 文件已存在时在文件名后加上序号，如 scan_p1_1 (2).png，避免覆盖已有文件。
+pub fn unique_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2u64..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
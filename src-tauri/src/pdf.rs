@@ -1,27 +1,47 @@
-use image::{ColorType, DynamicImage, GenericImageView, ImageFormat, Pixel, RgbImage};
-use lopdf::{dictionary, ObjectId};
+use fax::encoder::Encoder;
+use fax::{Color, VecWriter};
+use image::{DynamicImage, GenericImageView, ImageFormat, Rgb, RgbImage};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, ObjectId, StringFormat};
 use lopdf::{Dictionary, Document, Object, Result, Stream};
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::prelude::*;
-use std::path::PathBuf;
-
-use crate::image::{scale, ImageSize};
+use std::path::{Path, PathBuf};
+use time::macros::format_description;
+
+use crate::font::{helvetica, helvetica_supports, helvetica_width, EmbeddedFont};
+use crate::image::{
+    contact_sheet, read_animation_frames, read_dpi, read_icc_profile, read_jpeg_components,
+    read_jpx_size, read_orientation, read_png_data, read_png_header, read_tiff_frames, scale,
+    scale_down, scale_to_cover, Dpi, ExifOrientation, FrameData, ImageFrame, ImageSize,
+    JpegComponents, PngData, DEFAULT_DPI,
+};
+use crate::metadata::{now, text_string, Metadata};
 use crate::models;
+use crate::path::{create_dir, is_pdf};
+
+/// 说明文字占用的高度与字号的比例，包括文字和图片之间的间距
+const CAPTION_LINE_HEIGHT: f32 = 1.3;
+
+/// 平铺水印的最小间隔，单位为点
+const MIN_WATERMARK_STEP: f32 = 1.0;
+
+/// 每个页面最多平铺的水印数量
+const MAX_WATERMARK_TILES: f32 = 10_000.0;
 
+/// 图片左下角在页面中的坐标，图片被裁剪时可能为负数。
 #[derive(Debug)]
 struct Position {
-    x: u32,
-    y: u32,
+    x: i32,
+    y: i32,
 }
 
-impl From<Position> for (u32, u32) {
-    fn from(value: Position) -> Self {
-        (value.x as u32, value.y as u32)
-    }
-}
-
-impl From<(u32, u32)> for Position {
-    fn from(value: (u32, u32)) -> Self {
+impl From<(i32, i32)> for Position {
+    fn from(value: (i32, i32)) -> Self {
         Position {
             x: value.0,
             y: value.1,
@@ -32,8 +52,8 @@ impl From<(u32, u32)> for Position {
 impl From<(f32, f32)> for Position {
     fn from(value: (f32, f32)) -> Self {
         Position {
-            x: value.0.round() as u32,
-            y: value.1.round() as u32,
+            x: value.0.round() as i32,
+            y: value.1.round() as i32,
         }
     }
 }
@@ -44,12 +64,30 @@ impl Into<(f32, f32)> for Position {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct PageSize {
     pub width: u32,
     pub height: u32,
 }
 
+impl PageSize {
+    /// 竖向页面，高不小于宽。
+    fn portrait(&self) -> PageSize {
+        PageSize {
+            width: self.width.min(self.height),
+            height: self.width.max(self.height),
+        }
+    }
+
+    /// 横向页面，宽不小于高。
+    fn landscape(&self) -> PageSize {
+        PageSize {
+            width: self.width.max(self.height),
+            height: self.width.min(self.height),
+        }
+    }
+}
+
 impl From<(u32, u32)> for PageSize {
     fn from(value: (u32, u32)) -> Self {
         PageSize {
@@ -77,308 +115,3427 @@ impl From<&PageSize> for ImageSize {
     }
 }
 
-#[derive(Debug)]
+/// 页面类型，即纸张尺寸。
+///
+/// 包括 ISO 216 的 A、B 系列，JIS 的 B 系列和 ANSI 的常用尺寸。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
 pub enum PageType {
-    // Letter,
-    // A0,
-    // A1,
-    // A2,
-    // A3,
+    Letter,
+    Legal,
+    Tabloid,
+    A0,
+    A1,
+    A2,
+    A3,
+    #[default]
     A4,
-    // A5,
-    // A6,
-    // B0,
-    // B1,
-    // B2,
-    // B3,
-    // B4,
-    // B5,
-    // B6,
-}
-
-pub fn page_size(page_type: &PageType) -> PageSize {
-    match *page_type {
-        // PageType::Letter => PageSize(612.0, 792.0),
+    A5,
+    A6,
+    B0,
+    B1,
+    B2,
+    B3,
+    B4,
+    B5,
+    B6,
+    JisB0,
+    JisB1,
+    JisB2,
+    JisB3,
+    JisB4,
+    JisB5,
+    JisB6,
+    /// 页面尺寸与每张图片的实际尺寸相同，由图片像素和分辨率决定
+    Image,
+}
+
+/// 页面尺寸，单位为 pdf 单元（1/72 inch）。
+///
+/// 页面尺寸由图片决定时返回 `None`。
+// 尺寸由毫米换算而来，保留原始精度
+#[allow(clippy::excessive_precision)]
+pub fn page_size(page_type: &PageType) -> Option<PageSize> {
+    let page_size = match *page_type {
+        PageType::Letter => PageSize::from((612.0, 792.0)),
+        PageType::Legal => PageSize::from((612.0, 1008.0)),
+        PageType::Tabloid => PageSize::from((792.0, 1224.0)),
+        PageType::A0 => PageSize::from((2383.937, 3370.3937)),
+        PageType::A1 => PageSize::from((1683.7795, 2383.937)),
+        PageType::A2 => PageSize::from((1190.5512, 1683.7795)),
+        PageType::A3 => PageSize::from((841.8898, 1190.5512)),
         PageType::A4 => PageSize::from((595.2756, 841.8898)),
-    }
+        PageType::A5 => PageSize::from((419.5276, 595.2756)),
+        PageType::A6 => PageSize::from((297.6378, 419.5276)),
+        PageType::B0 => PageSize::from((2834.6457, 4008.189)),
+        PageType::B1 => PageSize::from((2004.0945, 2834.6457)),
+        PageType::B2 => PageSize::from((1417.3228, 2004.0945)),
+        PageType::B3 => PageSize::from((1000.6299, 1417.3228)),
+        PageType::B4 => PageSize::from((708.6614, 1000.6299)),
+        PageType::B5 => PageSize::from((498.8976, 708.6614)),
+        PageType::B6 => PageSize::from((354.3307, 498.8976)),
+        PageType::JisB0 => PageSize::from((2919.685, 4127.2441)),
+        PageType::JisB1 => PageSize::from((2063.622, 2919.685)),
+        PageType::JisB2 => PageSize::from((1459.8425, 2063.622)),
+        PageType::JisB3 => PageSize::from((1031.811, 1459.8425)),
+        PageType::JisB4 => PageSize::from((728.5039, 1031.811)),
+        PageType::JisB5 => PageSize::from((515.9055, 728.5039)),
+        PageType::JisB6 => PageSize::from((362.8346, 515.9055)),
+        PageType::Image => return None,
+    };
+
+    Some(page_size)
 }
 
-struct ImageObject {}
+/// 页面方向。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
+    /// 根据每张图片的宽高比决定页面方向
+    Auto,
+}
 
-impl ImageObject {
-    async fn new(path: PathBuf) -> Result<(Stream, ImageSize)> {
-        let mut file = File::open(&path)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
+/// 长度单位。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum Unit {
+    /// pdf 单元，1/72 inch
+    #[default]
+    Pt,
+    Mm,
+}
 
-        debug!("处理一张 pdf 图片: {:?}", path);
+/// 毫米转换为 pdf 单元。
+fn mm_to_pt(mm: f32) -> f32 {
+    mm * 72.0 / 25.4
+}
 
-        Self::image_from(buffer).await
-    }
+/// 页边距。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Margins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub unit: Unit,
+}
 
-    fn color_space(color_type: ColorType) -> Vec<u8> {
-        match color_type {
-            ColorType::L8 => b"DeviceGray".to_vec(),
-            ColorType::La8 => b"DeviceGray".to_vec(),
-            ColorType::Rgb8 => b"DeviceRGB".to_vec(),
-            ColorType::Rgb16 => b"DeviceRGB".to_vec(),
-            ColorType::La16 => b"DeviceN".to_vec(),
-            ColorType::Rgba8 => b"DeviceRGB".to_vec(),
-            ColorType::Rgba16 => b"DeviceN".to_vec(),
-            _ => b"Indexed".to_vec(),
+impl Margins {
+    /// 统一转换为以 pdf 单元表示的页边距。
+    fn to_pt(self) -> Margins {
+        match self.unit {
+            Unit::Pt => self,
+            Unit::Mm => Margins {
+                top: mm_to_pt(self.top),
+                right: mm_to_pt(self.right),
+                bottom: mm_to_pt(self.bottom),
+                left: mm_to_pt(self.left),
+                unit: Unit::Pt,
+            },
         }
     }
+}
 
-    fn set_image_dict(width: u32, height: u32, color_space: Vec<u8>, bits: u16) -> Dictionary {
-        let mut dict = Dictionary::new();
-        dict.set("Type", Object::Name(b"XObject".to_vec()));
-        dict.set("Subtype", Object::Name(b"Image".to_vec()));
-        dict.set("Width", width);
-        dict.set("Height", height);
-        dict.set("ColorSpace", Object::Name(color_space));
-        dict.set("BitsPerComponent", bits);
+/// 每页放置多张图片时的网格布局，图片按行从左上角开始排列。
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub rows: u32,
+    pub columns: u32,
+    /// 单元格之间的间距
+    pub gutter: f32,
+    pub unit: Unit,
+}
 
-        dict
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            rows: 1,
+            columns: 1,
+            gutter: 0.0,
+            unit: Unit::Pt,
+        }
     }
+}
 
-    async fn process_png(
-        img: DynamicImage,
-        bits: u16,
-        mut dict: Dictionary,
-        width: u32,
-        height: u32,
-    ) -> (Stream, ImageSize) {
-        // NOTE: png 图片保存到 pdf 中时不保留其 alpha 通道。
+impl Layout {
+    /// 每页的单元格数量。
+    fn cells(&self) -> usize {
+        (self.rows * self.columns) as usize
+    }
 
-        // png 是 rgba 通道时，位深可能是 10 位，pdf 只能使用 8 位或 16 位。
-        // 从图片中提取出位深不是 8 或 16 时将其设置为 8。
-        if bits != 8 && bits != 16 {
-            dict.set("BitsPerComponent", 8);
+    /// 统一转换为以 pdf 单元表示的间距。
+    fn to_pt(self) -> Layout {
+        match self.unit {
+            Unit::Pt => self,
+            Unit::Mm => Layout {
+                gutter: mm_to_pt(self.gutter),
+                unit: Unit::Pt,
+                ..self
+            },
         }
+    }
+}
 
-        let (w, h) = img.dimensions();
-        let mut output = RgbImage::new(w, h);
-        for (x, y, pixel) in img.pixels() {
-            output.put_pixel(
-                x,
-                y,
-                // pixel.map will iterate over the r, g, b, a values of the pixel
-                pixel.to_rgb(),
-            );
+/// 图片在可用区域中的对齐方式。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum Alignment {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    CenterLeft,
+    #[default]
+    Center,
+    CenterRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+/// 页面中去除页边距后可放置图片的区域，原点在页面左下角。
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl From<&Area> for ImageSize {
+    fn from(value: &Area) -> Self {
+        ImageSize::from((value.width, value.height))
+    }
+}
+
+impl Area {
+    /// 按对齐方式计算图片在区域中的坐标。
+    fn align(&self, size: &ImageSize, alignment: Alignment) -> Position {
+        let free_width = self.width - size.width as f32;
+        let free_height = self.height - size.height as f32;
+
+        let x = match alignment {
+            Alignment::TopLeft | Alignment::CenterLeft | Alignment::BottomLeft => self.x,
+            Alignment::TopCenter | Alignment::Center | Alignment::BottomCenter => {
+                self.x + free_width / 2.0
+            }
+            Alignment::TopRight | Alignment::CenterRight | Alignment::BottomRight => {
+                self.x + free_width
+            }
+        };
+
+        // pdf 的 y 轴向上
+        let y = match alignment {
+            Alignment::TopLeft | Alignment::TopCenter | Alignment::TopRight => self.y + free_height,
+            Alignment::CenterLeft | Alignment::Center | Alignment::CenterRight => {
+                self.y + free_height / 2.0
+            }
+            Alignment::BottomLeft | Alignment::BottomCenter | Alignment::BottomRight => self.y,
+        };
+
+        Position::from((x, y))
+    }
+
+    /// 去除底部指定高度后的区域。
+    fn above(&self, height: f32) -> Area {
+        Area {
+            x: self.x,
+            y: self.y + height,
+            width: self.width,
+            height: self.height - height,
         }
+    }
 
-        let mut img_object = Stream::new(dict, output.to_vec());
-        // Ignore any compression error.
-        let _ = img_object.compress();
+    /// 区域按网格布局划分后的第 index 个单元格。
+    fn cell(&self, layout: &Layout, index: usize) -> Area {
+        let columns = layout.columns as f32;
+        let rows = layout.rows as f32;
+
+        let width = (self.width - layout.gutter * (columns - 1.0)) / columns;
+        let height = (self.height - layout.gutter * (rows - 1.0)) / rows;
 
-        (img_object, ImageSize::from((width, height)))
+        let column = (index % layout.columns as usize) as f32;
+        let row = (index / layout.columns as usize) as f32;
 
-        // NOTE: 如果上面的逻辑仍有问题，后续改用转换为 jpg 后递归完成。
-        // output
-        //     .save_with_format("output.jpg", ImageFormat::Jpeg)
-        //     .unwrap();
-        // return Self::new("output.jpg");
+        // 第一行在区域顶部
+        Area {
+            x: self.x + column * (width + layout.gutter),
+            y: self.y + self.height - (row + 1.0) * height - row * layout.gutter,
+            width,
+            height,
+        }
     }
+}
 
-    async fn image_from(buffer: Vec<u8>) -> Result<(Stream, ImageSize)> {
-        let img = image::load_from_memory(buffer.as_ref())?;
+/// 图片在页面中的缩放方式。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum FitMode {
+    /// 等比缩放到完全放入页面
+    #[default]
+    Contain,
+    /// 等比缩放到铺满页面，超出的部分被裁剪
+    Cover,
+    /// 拉伸到与页面相同的尺寸，不保持宽高比
+    Stretch,
+    /// 与 Contain 相同，但不放大小图片
+    NeverUpscale,
+    /// 按图片分辨率放置为实际物理尺寸，超出页面的部分被裁剪
+    ActualSize,
+}
 
-        let (width, height) = img.dimensions();
-        debug!("图片尺寸：width={}, height={}", width, height);
+/// 图片下方的说明文字。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Captions {
+    /// 图片没有指定说明文字时是否显示文件名
+    pub file_name: bool,
+    /// 字号，文字超出单元格宽度时会缩小
+    pub font_size: f32,
+    /// 绘制中文等非 ASCII 文字使用的字体文件，为空时查找系统中文字体
+    pub font: Option<PathBuf>,
+}
 
-        // It looks like Adobe Illustrator uses a predictor offset of 2 bytes rather than 1 byte as
-        // the PNG specification suggests. This seems to come from the fact that the PNG specification
-        // doesn't allow 4-bit color images (only 8-bit and 16-bit color). With 1-bit, 2-bit and 4-bit
-        // mono images there isn't the same problem because there's only one component.
-        let bits = img.color().bits_per_pixel() / 3;
-        debug!("图片色深为 {}", bits);
+impl Default for Captions {
+    fn default() -> Self {
+        Captions {
+            file_name: false,
+            font_size: 10.0,
+            font: None,
+        }
+    }
+}
 
-        let color_type = img.color();
-        debug!("图片色彩类型为 {:?}", color_type);
+/// 页眉或页脚中文字的水平对齐方式。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum TextAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
 
-        let color_space = Self::color_space(color_type);
+/// 页眉或页脚。
+///
+/// 模板中的 {page}、{total}、{filename} 和 {date} 分别替换为页码、总页数、页面中第一张图片的文件名和当前日期。
+/// 从 pdf 文件复制的页面保持原样，不添加页眉和页脚，也不计入页码和总页数。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PageText {
+    pub template: String,
+    pub align: TextAlign,
+    pub font_size: f32,
+    pub color: [u8; 3],
+}
 
-        let mut dict = Self::set_image_dict(width, height, color_space, bits);
+impl Default for PageText {
+    fn default() -> Self {
+        PageText {
+            template: String::new(),
+            align: TextAlign::Center,
+            font_size: 10.0,
+            color: [0, 0, 0],
+        }
+    }
+}
 
-        let image_fmt = match image::guess_format(buffer.as_ref()) {
-            Ok(format) => format,
-            Err(err) => {
-                warn!("获取图片格式失败，尝试直接返回图片对象：{}", err);
+/// 水印的内容，文字或图片文件。
+#[derive(Debug, Clone, Deserialize)]
+pub enum WatermarkContent {
+    Text(String),
+    Image(PathBuf),
+}
 
-                let mut img_object = Stream::new(dict, img.into_bytes());
-                // Ignore any compression error.
-                let _ = img_object.compress();
-                return Ok((img_object, ImageSize::from((width, height))));
-            }
-        };
-        debug!("图片格式为 {:?}", image_fmt);
+/// 水印。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Watermark {
+    pub content: WatermarkContent,
+    /// 文字水印的字号
+    pub font_size: f32,
+    /// 文字水印的颜色
+    pub color: [u8; 3],
+    /// 图片水印的宽度，单位为 pdf 单元，为空时按图片分辨率计算
+    pub width: Option<f32>,
+    /// 逆时针旋转角度
+    pub rotate: f32,
+    /// 不透明度，0～1
+    pub opacity: f32,
+    /// 是否平铺满整个页面，否则只在页面中心放置一个
+    pub tiled: bool,
+    /// 平铺时水印之间的间距，单位为 pdf 单元
+    pub gap: f32,
+    /// 是否绘制在图片上方
+    pub above: bool,
+}
 
-        match image_fmt {
-            ImageFormat::Jpeg => {
-                dict.set("Filter", Object::Name(b"DCTDecode".to_vec()));
-                return Ok((Stream::new(dict, buffer), ImageSize::from((width, height))));
-            }
-            ImageFormat::Png => Ok(Self::process_png(img, bits, dict, width, height).await),
-            _ => {
-                let mut img_object = Stream::new(dict, img.into_bytes());
-                // Ignore any compression error.
-                let _ = img_object.compress();
-                return Ok((img_object, ImageSize::from((width, height))));
-            }
+impl Default for Watermark {
+    fn default() -> Self {
+        Watermark {
+            content: WatermarkContent::Text(String::new()),
+            font_size: 48.0,
+            color: [128, 128, 128],
+            width: None,
+            rotate: 45.0,
+            opacity: 0.3,
+            tiled: false,
+            gap: 48.0,
+            above: true,
         }
     }
 }
 
-struct PDF {
-    doc: Document,
-    pages_id: ObjectId,
-    page_size: PageSize,
+/// 书签的生成方式。
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+pub enum OutlineMode {
+    /// 不生成书签
+    #[default]
+    None,
+    /// 每张图片一个书签，标题为文件名
+    Images,
+    /// 按图片所在的目录生成多层书签，从所有图片共同的上级目录开始，同一目录的图片放在同一个目录书签下
+    Folders,
 }
 
-impl PDF {
-    pub fn new(page_type: PageType) -> PDF {
-        let mut doc = Document::with_version("1.5");
+/// 页码标签的编号样式。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum LabelStyle {
+    /// 阿拉伯数字
+    #[default]
+    Decimal,
+    /// 大写罗马数字
+    UpperRoman,
+    /// 小写罗马数字
+    LowerRoman,
+    /// 大写字母，Z 之后为 AA、BB
+    UpperLetters,
+    /// 小写字母
+    LowerLetters,
+    /// 只显示前缀
+    None,
+}
 
-        let pages_id = doc.new_object_id();
+/// 一段页码标签，从 start 页开始直到下一段之前的页面都使用此标签。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PageLabel {
+    /// 开始的页面，从 1 开始
+    pub start: usize,
+    pub style: LabelStyle,
+    /// 页码前缀，如 "A-"
+    pub prefix: Option<String>,
+    /// 第一页的编号
+    pub first: u32,
+}
 
-        let page_size = page_size(&page_type);
+impl Default for PageLabel {
+    fn default() -> Self {
+        PageLabel {
+            start: 1,
+            style: LabelStyle::Decimal,
+            prefix: None,
+            first: 1,
+        }
+    }
+}
 
-        debug!("创建一个新的 pdf 对象，页面类型 为 {:?}", page_type);
+impl PageLabel {
+    /// /PageLabels 数字树中的页码标签字典。
+    fn to_dictionary(&self) -> Dictionary {
+        let mut label = dictionary! {
+            "Type" => "PageLabel",
+        };
 
-        PDF {
-            doc,
-            pages_id,
-            page_size,
+        let style = match self.style {
+            LabelStyle::Decimal => Some("D"),
+            LabelStyle::UpperRoman => Some("R"),
+            LabelStyle::LowerRoman => Some("r"),
+            LabelStyle::UpperLetters => Some("A"),
+            LabelStyle::LowerLetters => Some("a"),
+            LabelStyle::None => None,
+        };
+        if let Some(style) = style {
+            label.set("S", style);
+        }
+        if let Some(prefix) = &self.prefix {
+            label.set("P", text_string(prefix));
         }
+        if self.first != 1 {
+            label.set("St", self.first as i64);
+        }
+
+        label
     }
+}
 
-    fn add_blank_page(&mut self) -> ObjectId {
-        // 需要有一个空 content 占位
-        let content_id = self.doc.add_object(Stream::new(dictionary! {}, vec![]));
+/// 书签树中的一项。
+struct OutlineNode {
+    title: String,
+    /// 书签指向的页面
+    page_id: ObjectId,
+    children: Vec<OutlineNode>,
+}
 
-        let page_id = self.doc.add_object(dictionary! {
-            "Type" => "Page",
-            "Parent" => self.pages_id,
-            "MediaBox" => vec![0.0.into(), 0.0.into(), self.page_size.width.into(), self.page_size.height.into()],
-            "Contents" => content_id,
-        });
+/// 动图（gif、webp、apng）的处理方式。
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum AnimationMode {
+    /// 只使用第一帧
+    #[default]
+    First,
+    /// 每一帧各占一页
+    All,
+    /// 所有帧排列成网格放在同一页
+    ContactSheet,
+}
 
-        debug!("添加一个空页面 {:?}", page_id);
+/// 图片的顺时针旋转角度。
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rotation {
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
 
-        page_id
+impl TryFrom<i16> for Rotation {
+    type Error = String;
+
+    fn try_from(value: i16) -> std::result::Result<Self, Self::Error> {
+        match value.rem_euclid(360) {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Clockwise90),
+            180 => Ok(Rotation::Clockwise180),
+            270 => Ok(Rotation::Clockwise270),
+            _ => {
+                error!("不支持的旋转角度：{}", value);
+                Err(format!("旋转角度必需是 90 的倍数：{}", value))
+            }
+        }
     }
+}
 
-    fn scale(&self, image_size: &ImageSize) -> ImageSize {
-        scale(image_size, &ImageSize::from(&self.page_size))
+impl Rotation {
+    fn quarter_turns(&self) -> u8 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::Clockwise270 => 3,
+        }
     }
 
-    fn insert_image(
-        &mut self,
-        page_id: ObjectId,
-        image_stream: Stream,
-        position: Position,
-        size: ImageSize,
-    ) -> Result<()> {
-        self.doc
-            .insert_image(page_id, image_stream, position.into(), size.into())
+    fn from_quarter_turns(turns: u8) -> Rotation {
+        match turns % 4 {
+            1 => Rotation::Clockwise90,
+            2 => Rotation::Clockwise180,
+            3 => Rotation::Clockwise270,
+            _ => Rotation::None,
+        }
     }
 
-    fn insert_pages(&mut self, pages: Dictionary) {
-        self.doc
-            .objects
-            .insert(self.pages_id, Object::Dictionary(pages));
+    /// 在当前旋转的基础上继续旋转。
+    fn then(self, other: Rotation) -> Rotation {
+        Rotation::from_quarter_turns(self.quarter_turns() + other.quarter_turns())
+    }
 
-        trace!("添加所有页面");
+    /// 旋转后宽高是否互换。
+    fn swaps_size(&self) -> bool {
+        matches!(self, Rotation::Clockwise90 | Rotation::Clockwise270)
     }
 
-    fn create_catalog(&mut self) {
-        let catalog_id = self.doc.add_object(dictionary! {
-            "Type" => "Catalog",
-            "Pages" => self.pages_id,
-        });
-        trace!("已创建目录 {:?}", catalog_id);
+    /// 把图片绘制到以 position 为左下角、size 为旋转后尺寸的区域中的变换矩阵。
+    ///
+    /// mirror 为 true 时先水平翻转图片再旋转。
+    fn matrix(&self, position: Position, size: ImageSize, mirror: bool) -> [f32; 6] {
+        let (x, y): (f32, f32) = position.into();
+        let (w, h): (f32, f32) = size.into();
+
+        let [a, b, c, d, e, f] = match self {
+            Rotation::None => [w, 0.0, 0.0, h, x, y],
+            Rotation::Clockwise90 => [0.0, -h, w, 0.0, x, y + h],
+            Rotation::Clockwise180 => [-w, 0.0, 0.0, -h, x + w, y + h],
+            Rotation::Clockwise270 => [0.0, h, -w, 0.0, x + w, y],
+        };
 
-        self.doc.trailer.set("Root", catalog_id);
-        debug!("已添加目录 {:?}", catalog_id);
+        if !mirror {
+            return [a, b, c, d, e, f];
+        }
 
-        self.doc.compress();
-        trace!("文档已压缩");
+        // 水平翻转为 [-1 0 0 1 1 0]，与旋转矩阵相乘
+        [-a, -b, c, d, a + e, b + f]
     }
+}
 
-    fn save(&mut self, output: PathBuf) -> std::io::Result<()> {
-        match self.doc.save(&output) {
-            Ok(_) => {
-                info!("已保存 pdf 文件：{:?}", output);
-                Ok(())
-            }
-            Err(e) => {
-                error!("保存文件时出错：{}", e);
-                return Err(e);
-            }
+/// 处理单张图片时使用的选项。
+#[derive(Debug, Clone, Copy)]
+struct EmbedOptions {
+    /// 透明图片的背景色，为空时保留透明通道
+    background: Option<[u8; 3]>,
+    /// 黑白图片是否使用 CCITT G4 编码
+    ccitt: bool,
+    /// 动图的处理方式
+    animation: AnimationMode,
+}
+
+impl From<&models::MergeOptions> for EmbedOptions {
+    fn from(value: &models::MergeOptions) -> Self {
+        EmbedOptions {
+            background: value.background,
+            ccitt: value.ccitt,
+            animation: value.animation,
         }
     }
 }
 
-/// 把图片嵌入 pdf。
-///
-/// 使用并发添加图片，并发时需要保存图片顺序不能错乱。
-pub async fn embedd_images_to_new_pdf(
-    output: PathBuf,
-    images: Vec<models::Image>,
-) -> std::result::Result<(), String> {
-    let mut pdf = PDF::new(PageType::A4);
+/// 按 pdf 要求排列的图片数据。
+struct RawImage {
+    color_space: &'static [u8],
+    bits: u16,
+    /// 颜色数据，16 位采样为大端序
+    data: Vec<u8>,
+    /// 与颜色数据位深相同的透明通道
+    alpha: Option<Vec<u8>>,
+}
 
-    let mut tasks = Vec::with_capacity(images.len());
+/// 把交错排列的采样拆分为颜色和最后一个透明通道。
+fn split_channels<T: Copy>(samples: &[T], channels: usize) -> (Vec<T>, Vec<T>) {
+    let mut color = Vec::with_capacity(samples.len() / channels * (channels - 1));
+    let mut alpha = Vec::with_capacity(samples.len() / channels);
 
-    for image in images.iter() {
-        let page_id = pdf.add_blank_page();
-        let path = image.path.clone();
-        tasks.push((page_id, &image.path, tokio::spawn(ImageObject::new(path))));
+    for pixel in samples.chunks_exact(channels) {
+        color.extend_from_slice(&pixel[..channels - 1]);
+        alpha.push(pixel[channels - 1]);
     }
 
-    let mut page_ids: Vec<Object> = Vec::with_capacity(images.len());
+    (color, alpha)
+}
 
-    for (page_id, ip, task) in tasks {
-        let (stream, image_size) = task
-            .await
-            .map_err(|err| {
-                error!("并发 join 时出错: {}", err);
+/// pdf 中 16 位的采样使用大端序。
+fn to_be_bytes(samples: &[u16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_be_bytes()).collect()
+}
 
-                return err.to_string();
-            })?
-            .map_err(|e| e.to_string())?;
+impl From<DynamicImage> for RawImage {
+    fn from(img: DynamicImage) -> Self {
+        let (color_space, bits, data, alpha): (&'static [u8], u16, Vec<u8>, Option<Vec<u8>>) =
+            match img {
+                DynamicImage::ImageLuma8(buf) => (b"DeviceGray", 8, buf.into_raw(), None),
+                DynamicImage::ImageLumaA8(buf) => {
+                    let (data, alpha) = split_channels(buf.as_raw(), 2);
+                    (b"DeviceGray", 8, data, Some(alpha))
+                }
+                DynamicImage::ImageRgb8(buf) => (b"DeviceRGB", 8, buf.into_raw(), None),
+                DynamicImage::ImageRgba8(buf) => {
+                    let (data, alpha) = split_channels(buf.as_raw(), 4);
+                    (b"DeviceRGB", 8, data, Some(alpha))
+                }
+                DynamicImage::ImageLuma16(buf) => {
+                    (b"DeviceGray", 16, to_be_bytes(buf.as_raw()), None)
+                }
+                DynamicImage::ImageLumaA16(buf) => {
+                    let (data, alpha) = split_channels(buf.as_raw(), 2);
+                    (
+                        b"DeviceGray",
+                        16,
+                        to_be_bytes(&data),
+                        Some(to_be_bytes(&alpha)),
+                    )
+                }
+                DynamicImage::ImageRgb16(buf) => {
+                    (b"DeviceRGB", 16, to_be_bytes(buf.as_raw()), None)
+                }
+                DynamicImage::ImageRgba16(buf) => {
+                    let (data, alpha) = split_channels(buf.as_raw(), 4);
+                    (
+                        b"DeviceRGB",
+                        16,
+                        to_be_bytes(&data),
+                        Some(to_be_bytes(&alpha)),
+                    )
+                }
+                // 浮点等 pdf 不支持的格式转换为 8 位
+                img if img.color().has_alpha() => {
+                    return RawImage::from(DynamicImage::ImageRgba8(img.into_rgba8()))
+                }
+                img => return RawImage::from(DynamicImage::ImageRgb8(img.into_rgb8())),
+            };
+
+        RawImage {
+            color_space,
+            bits,
+            data,
+            alpha,
+        }
+    }
+}
+
+struct ImageObject {
+    stream: Stream,
+    /// 透明通道，插入 pdf 时作为图片的 /SMask
+    smask: Option<Stream>,
+    size: ImageSize,
+    /// 图片文件中记录的分辨率
+    dpi: Option<Dpi>,
+    /// 绘制时的旋转，包括 EXIF 方向和用户指定的旋转
+    rotation: Rotation,
+    /// 绘制时是否水平翻转，来自 EXIF 方向
+    mirror: bool,
+    /// 图片中嵌入的 ICC 配置文件
+    icc_profile: Option<Vec<u8>>,
+}
+
+impl ImageObject {
+    /// 读取一张图片，多页 tiff 的每一页和动图展开后的每一帧都是一个图片对象。
+    async fn new(path: PathBuf, options: EmbedOptions) -> Result<Vec<ImageObject>> {
+        let mut file = File::open(&path)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+
+        debug!("处理一张 pdf 图片: {:?}", path);
 
-        let scaled = pdf.scale(&image_size);
-        debug!("图片缩放尺寸 {:?} -> {:?}", image_size, scaled);
+        // tiff 逐页读取，CCITT G4 和 CMYK 页面不需要经过 image 解码
+        let frames = if matches!(image::guess_format(&buffer), Ok(ImageFormat::Tiff)) {
+            let frames = read_tiff_frames(&buffer)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            debug!("tiff 共 {} 页：{:?}", frames.len(), path);
 
-        let position = Position::from((
-            (pdf.page_size.width as f32 - scaled.width as f32) / 2.0,
-            (pdf.page_size.height as f32 - scaled.height as f32) / 2.0,
-        ));
-        debug!("图片在 pdf 中的坐标 {:?}", position);
+            Some(frames)
+        } else {
+            Self::animation_frames(&buffer, options.animation)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?
+        };
 
-        pdf.insert_image(page_id, stream, position, scaled)
-            .map_err(|err| err.to_string())?;
+        let frames = match frames {
+            Some(frames) => frames,
+            None => return Ok(vec![Self::image_from(buffer, options).await?]),
+        };
 
-        debug!("已向 pdf 插入图片：{:?}", ip);
+        let mut objects = Vec::with_capacity(frames.len());
+        for frame in frames {
+            objects.push(Self::frame_from(frame, options).await);
+        }
 
-        page_ids.push(page_id.into());
+        Ok(objects)
     }
 
-    let pages = dictionary! {
-        "Type" => "Pages",
-        "Kids" => page_ids,
-        "Count" => Object::Integer(images.len() as i64),
-    };
+    /// 按处理方式展开动图，不是动图或只使用第一帧时返回 `None`。
+    fn animation_frames(
+        buffer: &[u8],
+        mode: AnimationMode,
+    ) -> crate::error::Result<Option<Vec<ImageFrame>>> {
+        if let AnimationMode::First = mode {
+            return Ok(None);
+        }
 
-    // 必需插入 pages
-    pdf.insert_pages(pages);
+        let frames = match read_animation_frames(buffer)? {
+            Some(frames) => frames,
+            None => return Ok(None),
+        };
+        debug!("动图共 {} 帧，处理方式 {:?}", frames.len(), mode);
 
-    // 必需有目录对象，即使目录不显示
-    pdf.create_catalog();
+        let images = match mode {
+            AnimationMode::ContactSheet => vec![contact_sheet(&frames)],
+            _ => frames,
+        };
 
-    match pdf.save(output) {
-        Ok(()) => Ok(()),
-        Err(e) => Err(e.to_string()),
+        // 动图的所有帧共用文件中记录的分辨率
+        let dpi = read_dpi(buffer);
+
+        Ok(Some(
+            images
+                .into_iter()
+                .map(|image| ImageFrame {
+                    data: FrameData::Decoded(DynamicImage::ImageRgba8(image)),
+                    dpi,
+                    orientation: ExifOrientation::default(),
+                    icc_profile: None,
+                })
+                .collect(),
+        ))
+    }
+
+    /// 旋转图片，同时更新旋转后图片的尺寸和分辨率。
+    fn rotate(&mut self, rotation: Rotation) {
+        self.rotation = self.rotation.then(rotation);
+
+        if !rotation.swaps_size() {
+            return;
+        }
+
+        self.size = ImageSize::from((self.size.height, self.size.width));
+        self.dpi = self.dpi.map(|dpi| Dpi { x: dpi.y, y: dpi.x });
+    }
+
+    fn set_image_dict(width: u32, height: u32, color_space: &[u8], bits: u16) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.set("Type", Object::Name(b"XObject".to_vec()));
+        dict.set("Subtype", Object::Name(b"Image".to_vec()));
+        dict.set("Width", width);
+        dict.set("Height", height);
+        dict.set("ColorSpace", Object::Name(color_space.to_vec()));
+        dict.set("BitsPerComponent", bits);
+
+        dict
+    }
+
+    /// 把图片按透明度混合到背景色上。
+    fn flatten(img: &DynamicImage, background: [u8; 3]) -> RgbImage {
+        let (w, h) = img.dimensions();
+        let mut output = RgbImage::new(w, h);
+
+        for (x, y, pixel) in img.pixels() {
+            let [r, g, b, a] = pixel.0;
+            let blend =
+                |c: u8, bg: u8| ((c as u16 * a as u16 + bg as u16 * (255 - a as u16)) / 255) as u8;
+
+            output.put_pixel(
+                x,
+                y,
+                Rgb([
+                    blend(r, background[0]),
+                    blend(g, background[1]),
+                    blend(b, background[2]),
+                ]),
+            );
+        }
+
+        output
+    }
+
+    /// 透明通道的图片对象，作为主图片的 /SMask 使用。
+    fn smask(width: u32, height: u32, alpha: Vec<u8>, bits: u16) -> Stream {
+        let dict = Self::set_image_dict(width, height, b"DeviceGray", bits);
+
+        let mut smask = Stream::new(dict, alpha);
+        // Ignore any compression error.
+        let _ = smask.compress();
+
+        smask
+    }
+
+    /// jpeg 不需要解码，直接使用 DCTDecode 嵌入。
+    fn process_jpeg(buffer: Vec<u8>, components: JpegComponents) -> Stream {
+        debug!("jpeg 颜色分量 {:?}", components);
+
+        let color_space: &[u8] = match components.count {
+            1 => b"DeviceGray",
+            4 => b"DeviceCMYK",
+            _ => b"DeviceRGB",
+        };
+
+        let mut dict = Self::set_image_dict(components.width, components.height, color_space, 8);
+        dict.set("Filter", Object::Name(b"DCTDecode".to_vec()));
+
+        // Photoshop 等软件保存的 CMYK jpeg 是反相存储的
+        if components.count == 4 && components.adobe {
+            dict.set(
+                "Decode",
+                [1, 0, 1, 0, 1, 0, 1, 0]
+                    .iter()
+                    .map(|&v| Object::Integer(v))
+                    .collect::<Vec<Object>>(),
+            );
+        }
+
+        Stream::new(dict, buffer)
+    }
+
+    /// 调色板 png 使用 /Indexed 颜色空间嵌入，不展开为 RGB。
+    ///
+    /// 不是调色板图片，或者需要把透明调色板图片混合到背景色上时返回 `None`。
+    fn process_indexed(
+        buffer: &[u8],
+        background: Option<[u8; 3]>,
+    ) -> Option<(Stream, Option<Stream>, ImageSize)> {
+        let mut decoder = png::Decoder::new(buffer);
+        // 保留调色板索引
+        decoder.set_transformations(png::Transformations::IDENTITY);
+
+        let mut reader = decoder.read_info().ok()?;
+        let info = reader.info();
+
+        if info.color_type != png::ColorType::Indexed {
+            return None;
+        }
+
+        let palette = info.palette.as_ref()?.to_vec();
+        let trns = info.trns.as_ref().map(|trns| trns.to_vec());
+        let bits = info.bit_depth as u8;
+        let (width, height) = info.size();
+
+        if trns.is_some() && background.is_some() {
+            return None;
+        }
+
+        let mut data = vec![0; reader.output_buffer_size()];
+        let frame = reader
+            .next_frame(&mut data)
+            .map_err(|err| {
+                error!("解码调色板图片时出错：{}", err);
+                err
+            })
+            .ok()?;
+        data.truncate(frame.buffer_size());
+
+        debug!("调色板图片，{} 种颜色，位深 {}", palette.len() / 3, bits);
+
+        let mut dict = Self::set_image_dict(width, height, b"Indexed", bits.into());
+        dict.set(
+            "ColorSpace",
+            vec![
+                Object::Name(b"Indexed".to_vec()),
+                Object::Name(b"DeviceRGB".to_vec()),
+                Object::Integer((palette.len() / 3) as i64 - 1),
+                Object::String(palette, StringFormat::Hexadecimal),
+            ],
+        );
+
+        // 透明调色板需要把每个像素的索引换成透明度
+        let smask = trns.map(|trns| {
+            let per_byte = (8 / bits) as usize;
+            let mask = ((1u16 << bits) - 1) as u8;
+            let mut alpha = Vec::with_capacity((width * height) as usize);
+
+            for row in data.chunks(frame.line_size) {
+                for x in 0..width as usize {
+                    let shift = (per_byte - 1 - x % per_byte) * bits as usize;
+                    let index = ((row[x / per_byte] >> shift) & mask) as usize;
+
+                    alpha.push(*trns.get(index).unwrap_or(&255));
+                }
+            }
+
+            Self::smask(width, height, alpha, 8)
+        });
+
+        let mut img_object = Stream::new(dict, data);
+        // Ignore any compression error.
+        let _ = img_object.compress();
+
+        Some((img_object, smask, ImageSize::from((width, height))))
+    }
+
+    /// JPEG 2000 不需要解码，直接使用 JPXDecode 嵌入。
+    ///
+    /// 颜色空间和位深由 JPEG 2000 数据自身决定，不能写入图片字典。
+    fn process_jpx(buffer: Vec<u8>, size: &ImageSize) -> Stream {
+        let mut dict = Self::set_image_dict(size.width, size.height, b"", 0);
+        dict.remove(b"ColorSpace");
+        dict.remove(b"BitsPerComponent");
+        dict.set("Filter", Object::Name(b"JPXDecode".to_vec()));
+
+        Stream::new(dict, buffer).with_compression(false)
+    }
+
+    /// 黑白图片使用 CCITT G4 编码，不是黑白图片时返回 `None`。
+    fn process_ccitt(img: &DynamicImage) -> Option<Stream> {
+        let img = match img {
+            DynamicImage::ImageLuma8(gray) => gray,
+            _ => return None,
+        };
+
+        if img.pixels().any(|p| p.0[0] != 0 && p.0[0] != 255) {
+            return None;
+        }
+
+        let width = u16::try_from(img.width()).ok()?;
+
+        let mut encoder = Encoder::new(VecWriter::new());
+        for row in img.rows() {
+            let pels = row.map(|p| {
+                if p.0[0] == 0 {
+                    Color::Black
+                } else {
+                    Color::White
+                }
+            });
+            encoder.encode_line(pels, width).ok()?;
+        }
+        let data = encoder.finish().ok()?.finish();
+
+        Some(Self::process_fax4(data, img.width(), img.height(), false))
+    }
+
+    /// 已使用 CCITT G4 编码的数据直接嵌入。
+    fn process_fax4(data: Vec<u8>, width: u32, height: u32, black_is_1: bool) -> Stream {
+        let mut params = dictionary! {
+            // K 小于 0 表示 G4 编码
+            "K" => -1,
+            "Columns" => width as i64,
+            "Rows" => height as i64,
+        };
+        if black_is_1 {
+            params.set("BlackIs1", true);
+        }
+
+        let mut dict = Self::set_image_dict(width, height, b"DeviceGray", 1);
+        dict.set("Filter", Object::Name(b"CCITTFaxDecode".to_vec()));
+        dict.set("DecodeParms", params);
+
+        Stream::new(dict, data).with_compression(false)
+    }
+
+    /// 8 位的 CMYK 采样使用 DeviceCMYK 嵌入。
+    fn process_cmyk(data: Vec<u8>, width: u32, height: u32) -> Stream {
+        let dict = Self::set_image_dict(width, height, b"DeviceCMYK", 8);
+
+        let mut img_object = Stream::new(dict, data);
+        // Ignore any compression error.
+        let _ = img_object.compress();
+
+        img_object
+    }
+
+    /// png 的图像数据直接使用 FlateDecode 和 png 预测器嵌入，不需要解码和重新压缩。
+    fn process_png(png: PngData) -> Stream {
+        let color_space: &[u8] = if png.colors == 1 {
+            b"DeviceGray"
+        } else {
+            b"DeviceRGB"
+        };
+
+        let mut dict =
+            Self::set_image_dict(png.width, png.height, color_space, png.bit_depth.into());
+        dict.set("Filter", Object::Name(b"FlateDecode".to_vec()));
+        dict.set(
+            "DecodeParms",
+            dictionary! {
+                // 15 表示每行使用各自的 png 过滤类型
+                "Predictor" => 15,
+                "Colors" => png.colors as i64,
+                "Columns" => png.width as i64,
+                "BitsPerComponent" => png.bit_depth as i64,
+            },
+        );
+
+        Stream::new(dict, png.idat).with_compression(false)
+    }
+
+    /// 嵌入解码后的图片，透明通道保存为 SMask 或混合到背景色上。
+    async fn process_decoded(img: DynamicImage, options: EmbedOptions) -> (Stream, Option<Stream>) {
+        let (w, h) = img.dimensions();
+
+        let raw = match options.background {
+            Some(bg) if img.color().has_alpha() => RawImage {
+                color_space: b"DeviceRGB",
+                bits: 8,
+                data: Self::flatten(&img, bg).into_raw(),
+                alpha: None,
+            },
+            _ => RawImage::from(img),
+        };
+        debug!(
+            "图片颜色空间 {}，位深 {}",
+            String::from_utf8_lossy(raw.color_space),
+            raw.bits
+        );
+
+        let dict = Self::set_image_dict(w, h, raw.color_space, raw.bits);
+
+        let mut img_object = Stream::new(dict, raw.data);
+        // Ignore any compression error.
+        let _ = img_object.compress();
+
+        (
+            img_object,
+            raw.alpha.map(|alpha| Self::smask(w, h, alpha, raw.bits)),
+        )
+    }
+
+    /// 不能直接嵌入的图片，解码后嵌入。
+    ///
+    /// `ccitt` 为 true 且解码后是黑白图片时使用 CCITT G4 编码。
+    async fn process_fallback(
+        buffer: &[u8],
+        options: EmbedOptions,
+        ccitt: bool,
+    ) -> Result<(Stream, Option<Stream>, ImageSize)> {
+        let img = image::load_from_memory(buffer)?;
+        let size = ImageSize::from(img.dimensions());
+        debug!("解码图片，色彩类型为 {:?}", img.color());
+
+        if ccitt {
+            if let Some(stream) = Self::process_ccitt(&img) {
+                debug!("黑白图片使用 CCITT G4 编码");
+                return Ok((stream, None, size));
+            }
+        }
+
+        let (stream, smask) = Self::process_decoded(img, options).await;
+
+        Ok((stream, smask, size))
+    }
+
+    /// 多页 tiff 的一页或动图的一帧，解码后嵌入。
+    async fn frame_from(frame: ImageFrame, options: EmbedOptions) -> ImageObject {
+        let (stream, smask, size) = match frame.data {
+            FrameData::Fax4 {
+                data,
+                width,
+                height,
+                black_is_1,
+            } => {
+                debug!("直接嵌入 tiff 的 CCITT G4 数据");
+                let stream = Self::process_fax4(data, width, height, black_is_1);
+                (stream, None, ImageSize::from((width, height)))
+            }
+            FrameData::Cmyk {
+                data,
+                width,
+                height,
+            } => {
+                debug!("CMYK 图片使用 DeviceCMYK 嵌入");
+                let stream = Self::process_cmyk(data, width, height);
+                (stream, None, ImageSize::from((width, height)))
+            }
+            FrameData::Decoded(image) => {
+                let size = ImageSize::from(image.dimensions());
+
+                let ccitt = if options.ccitt {
+                    Self::process_ccitt(&image)
+                } else {
+                    None
+                };
+
+                match ccitt {
+                    Some(stream) => {
+                        debug!("黑白图片使用 CCITT G4 编码");
+                        (stream, None, size)
+                    }
+                    None => {
+                        let (stream, smask) = Self::process_decoded(image, options).await;
+                        (stream, smask, size)
+                    }
+                }
+            }
+        };
+
+        let mut object = ImageObject {
+            stream,
+            smask,
+            size,
+            dpi: frame.dpi,
+            rotation: Rotation::None,
+            mirror: frame.orientation.mirror,
+            icc_profile: frame.icc_profile,
+        };
+
+        object.rotate(Rotation::try_from(frame.orientation.rotate).unwrap_or(Rotation::None));
+
+        object
+    }
+
+    async fn image_from(buffer: Vec<u8>, options: EmbedOptions) -> Result<ImageObject> {
+        if let Some(size) = read_jpx_size(&buffer) {
+            debug!("JPEG 2000 图片尺寸：{:?}", size);
+
+            return Ok(ImageObject {
+                stream: Self::process_jpx(buffer, &size),
+                smask: None,
+                size,
+                dpi: None,
+                rotation: Rotation::None,
+                mirror: false,
+                icc_profile: None,
+            });
+        }
+
+        let dpi = read_dpi(&buffer);
+        debug!("图片分辨率为 {:?}", dpi);
+
+        let orientation = read_orientation(&buffer);
+
+        let icc_profile = read_icc_profile(&buffer);
+
+        // jpeg 和 png 尽量直接嵌入，尺寸和颜色信息从文件头读取，不需要解码
+        let (stream, smask, size) = match image::guess_format(buffer.as_ref()) {
+            Ok(ImageFormat::Jpeg) => match read_jpeg_components(&buffer) {
+                // pdf 的 DCTDecode 只支持 8 位的 jpeg
+                Some(components) if components.bits == 8 => {
+                    debug!("图片格式为 {:?}", ImageFormat::Jpeg);
+                    let size = ImageSize::from((components.width, components.height));
+                    (Self::process_jpeg(buffer, components), None, size)
+                }
+                Some(components) => {
+                    warn!("jpeg 的位深为 {}，解码后嵌入", components.bits);
+                    Self::process_fallback(&buffer, options, false).await?
+                }
+                None => {
+                    warn!("未找到 jpeg 的 SOF 段，解码后嵌入");
+                    Self::process_fallback(&buffer, options, false).await?
+                }
+            },
+            Ok(ImageFormat::Png) => {
+                debug!("图片格式为 {:?}", ImageFormat::Png);
+                let header = read_png_header(&buffer);
+                debug!("png 文件头 {:?}", header);
+                // 只有 1 位灰度图片需要尝试 CCITT 编码
+                let bilevel = header.is_some_and(|header| header.is_bilevel());
+
+                if let Some(png) = read_png_data(&buffer) {
+                    debug!(
+                        "直接嵌入 png 数据，位深 {}，颜色分量 {}",
+                        png.bit_depth, png.colors
+                    );
+                    let size = ImageSize::from((png.width, png.height));
+                    (Self::process_png(png), None, size)
+                } else if let Some(indexed) = Self::process_indexed(&buffer, options.background) {
+                    indexed
+                } else {
+                    Self::process_fallback(&buffer, options, options.ccitt && bilevel).await?
+                }
+            }
+            other => {
+                match other {
+                    Ok(format) => debug!("图片格式为 {:?}", format),
+                    Err(err) => warn!("获取图片格式失败，尝试直接返回图片对象：{}", err),
+                }
+
+                Self::process_fallback(&buffer, options, options.ccitt).await?
+            }
+        };
+        debug!("图片尺寸：{:?}", size);
+
+        let mut object = ImageObject {
+            stream,
+            smask,
+            size,
+            dpi,
+            rotation: Rotation::None,
+            mirror: orientation.mirror,
+            icc_profile,
+        };
+
+        // jpeg 直接嵌入，不重新编码，所以 EXIF 方向通过绘制时的变换矩阵实现
+        object.rotate(Rotation::try_from(orientation.rotate).unwrap_or(Rotation::None));
+
+        Ok(object)
+    }
+}
+
+/// 页面内容流的绘制操作。
+#[derive(Default)]
+struct ContentBuilder {
+    operations: Vec<Operation>,
+}
+
+impl ContentBuilder {
+    fn push(&mut self, operator: &str, operands: Vec<Object>) {
+        self.operations.push(Operation::new(operator, operands));
+    }
+
+    fn save_state(&mut self) {
+        self.push("q", vec![]);
+    }
+
+    fn restore_state(&mut self) {
+        self.push("Q", vec![]);
+    }
+
+    /// 之后的绘制只显示在区域内的部分。
+    fn clip(&mut self, area: &Area) {
+        self.push(
+            "re",
+            vec![
+                area.x.into(),
+                area.y.into(),
+                area.width.into(),
+                area.height.into(),
+            ],
+        );
+        self.push("W", vec![]);
+        self.push("n", vec![]);
+    }
+
+    /// 变换之后绘制时使用的坐标系。
+    fn transform(&mut self, matrix: [f32; 6]) {
+        self.push("cm", matrix.iter().map(|&v| v.into()).collect());
+    }
+
+    fn graphics_state(&mut self, name: &[u8]) {
+        self.push("gs", vec![Object::Name(name.to_vec())]);
+    }
+
+    fn fill_color(&mut self, color: [u8; 3]) {
+        self.push(
+            "rg",
+            color.iter().map(|&c| (c as f32 / 255.0).into()).collect(),
+        );
+    }
+
+    fn draw_xobject(&mut self, name: &[u8]) {
+        self.push("Do", vec![Object::Name(name.to_vec())]);
+    }
+
+    /// 绘制一行已按字体编码的文字。
+    fn text(&mut self, font_name: &[u8], size: f32, position: Position, string: Object) {
+        self.push("BT", vec![]);
+        self.push("Tf", vec![Object::Name(font_name.to_vec()), size.into()]);
+        self.push("Td", vec![position.x.into(), position.y.into()]);
+        self.push("Tj", vec![string]);
+        self.push("ET", vec![]);
+    }
+
+    fn build(self) -> Content {
+        Content {
+            operations: self.operations,
+        }
+    }
+}
+
+/// 正在创建的页面，所有图片插入后才能绘制页码，所以内容流最后写入。
+struct Page {
+    id: ObjectId,
+    size: PageSize,
+    /// 页面中第一张图片的文件名
+    file_name: String,
+    content: ContentBuilder,
+}
+
+/// 已写入 pdf 的水印内容，每页复用相同的对象。
+enum WatermarkObject {
+    Text {
+        font_id: ObjectId,
+        string: Object,
+        width: f32,
+    },
+    Image {
+        id: ObjectId,
+        size: ImageSize,
+        rotation: Rotation,
+        mirror: bool,
+    },
+}
+
+/// 已准备好的水印。
+struct PreparedWatermark {
+    options: Watermark,
+    /// 设置不透明度的 /ExtGState
+    gs_id: ObjectId,
+    object: WatermarkObject,
+}
+
+struct PDF {
+    doc: Document,
+    pages_id: ObjectId,
+    /// 固定的页面尺寸，为空时每页的尺寸与其图片相同
+    page_size: Option<PageSize>,
+    orientation: Orientation,
+    margins: Margins,
+    alignment: Alignment,
+    fit_mode: FitMode,
+    layout: Layout,
+    /// 用户指定的分辨率，优先于图片中记录的分辨率
+    dpi: Option<Dpi>,
+    /// 已嵌入的 ICC 配置文件，相同的配置文件只嵌入一次
+    icc_profiles: HashMap<Vec<u8>, ObjectId>,
+    /// Helvetica 字体对象
+    helvetica_id: Option<ObjectId>,
+    /// 绘制非 ASCII 文字的内嵌字体，保存前才写入 pdf
+    embedded_font: Option<(ObjectId, EmbeddedFont)>,
+    /// 用户指定的字体文件
+    font_path: Option<PathBuf>,
+    /// 是否已经查找过内嵌字体
+    font_loaded: bool,
+    header: Option<PageText>,
+    footer: Option<PageText>,
+    watermark: Option<PreparedWatermark>,
+    metadata: Metadata,
+    outline_mode: OutlineMode,
+    /// 每张图片的书签，创建目录时按书签方式组织成树
+    bookmarks: Vec<(PathBuf, ObjectId)>,
+    page_labels: Vec<PageLabel>,
+    /// 从其他 pdf 导入的资源对象的摘要，相同的资源只保留一份
+    resources: HashMap<u64, ObjectId>,
+}
+
+impl PDF {
+    pub fn new(options: &models::MergeOptions) -> PDF {
+        let mut doc = Document::with_version("1.5");
+
+        let pages_id = doc.new_object_id();
+
+        let page_size = page_size(&options.page_type);
+
+        debug!("创建一个新的 pdf 对象，选项为 {:?}", options);
+
+        PDF {
+            doc,
+            pages_id,
+            page_size,
+            orientation: options.orientation,
+            margins: options.margins.to_pt(),
+            alignment: options.alignment,
+            fit_mode: options.fit_mode,
+            layout: options.layout.to_pt(),
+            dpi: options.dpi.map(Dpi::from),
+            icc_profiles: HashMap::new(),
+            helvetica_id: None,
+            embedded_font: None,
+            font_path: options.captions.font.clone(),
+            font_loaded: false,
+            header: options.header.clone(),
+            footer: options.footer.clone(),
+            watermark: None,
+            metadata: options.metadata.clone(),
+            outline_mode: options.outline,
+            bookmarks: Vec::new(),
+            page_labels: options.page_labels.clone(),
+            resources: HashMap::new(),
+        }
+    }
+
+    /// 加载已有的 pdf 文件，新页面先挂在根页面树节点下，插入时再调整。
+    pub fn load(path: &Path, options: &models::MergeOptions) -> std::result::Result<PDF, String> {
+        let mut doc = Document::load(path).map_err(|e| {
+            error!("读取 pdf 文件 {:?} 时出错：{}", path, e);
+            e.to_string()
+        })?;
+
+        if doc.is_encrypted() {
+            error!("不支持加密的 pdf 文件：{:?}", path);
+            return Err("不支持加密的 pdf 文件".to_string());
+        }
+
+        let pages_id = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Pages"))
+            .and_then(Object::as_reference)
+            .map_err(|e| {
+                error!("pdf 文件 {:?} 中没有页面树：{}", path, e);
+                e.to_string()
+            })?;
+
+        // 透明通道和 jpeg2000 图片需要 pdf 1.5
+        if doc.version.as_str() < "1.5" {
+            doc.version = "1.5".to_string();
+        }
+
+        debug!(
+            "已加载 pdf 文件 {:?}，共 {} 页",
+            path,
+            doc.get_pages().len()
+        );
+
+        let mut pdf = PDF::new(options);
+        pdf.doc = doc;
+        pdf.pages_id = pages_id;
+
+        Ok(pdf)
+    }
+
+    /// 把新页面插入已有文档的页面树，index 为第一个新页面的位置。
+    ///
+    /// 新页面和原来在此位置的页面放在同一个页面树节点中，并更新所有上级节点的页数。
+    fn insert_into_page_tree(
+        &mut self,
+        page_ids: &[ObjectId],
+        index: usize,
+    ) -> std::result::Result<(), String> {
+        let pages = self.doc.get_pages();
+
+        // 原来在此位置的页面，插入到末尾时为空
+        let next_page = pages.get(&(index as u32 + 1)).copied();
+
+        let parent_id = match next_page {
+            Some(page_id) => self
+                .doc
+                .get_dictionary(page_id)
+                .and_then(|page| page.get(b"Parent"))
+                .and_then(Object::as_reference)
+                .map_err(|e| {
+                    error!("页面 {:?} 没有上级节点：{}", page_id, e);
+                    e.to_string()
+                })?,
+            None => self.pages_id,
+        };
+
+        for page_id in page_ids {
+            let page = self
+                .doc
+                .get_dictionary_mut(*page_id)
+                .map_err(|e| e.to_string())?;
+            page.set("Parent", parent_id);
+
+            // 不继承上级节点的裁剪框和旋转角度
+            if !page.has(b"CropBox") {
+                if let Ok(media_box) = page.get(b"MediaBox").cloned() {
+                    page.set("CropBox", media_box);
+                }
+            }
+            if !page.has(b"Rotate") {
+                page.set("Rotate", 0);
+            }
+        }
+
+        let parent = self
+            .doc
+            .get_dictionary_mut(parent_id)
+            .map_err(|e| e.to_string())?;
+        let kids = parent
+            .get_mut(b"Kids")
+            .and_then(Object::as_array_mut)
+            .map_err(|e| {
+                error!("页面树节点 {:?} 没有 Kids：{}", parent_id, e);
+                e.to_string()
+            })?;
+
+        let position = next_page
+            .and_then(|page_id| {
+                kids.iter()
+                    .position(|kid| kid.as_reference().ok() == Some(page_id))
+            })
+            .unwrap_or(kids.len());
+        kids.splice(
+            position..position,
+            page_ids.iter().map(|id| Object::from(*id)),
+        );
+
+        // 逐级更新上级节点的页数，同时防止循环引用
+        let mut node_id = Some(parent_id);
+        let mut visited = Vec::new();
+        while let Some(id) = node_id.filter(|id| !visited.contains(id)) {
+            visited.push(id);
+
+            let node = self.doc.get_dictionary_mut(id).map_err(|e| e.to_string())?;
+            let count = node.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+            node.set("Count", count + page_ids.len() as i64);
+
+            node_id = node.get(b"Parent").and_then(Object::as_reference).ok();
+        }
+        debug!(
+            "已在第 {} 页插入 {} 个页面，上级节点 {:?}",
+            index + 1,
+            page_ids.len(),
+            parent_id
+        );
+
+        Ok(())
+    }
+
+    /// 复制 pdf 文件的所有页面，返回按顺序排列的页面。
+    ///
+    /// 对象重新编号后加入当前文档，只复制页面用到的对象，重复的资源只保留一份。
+    fn import_pdf(
+        &mut self,
+        path: &Path,
+        rotation: Rotation,
+    ) -> std::result::Result<Vec<ObjectId>, String> {
+        let mut doc = Document::load(path).map_err(|e| {
+            error!("读取 pdf 文件 {:?} 时出错：{}", path, e);
+            e.to_string()
+        })?;
+
+        if doc.is_encrypted() {
+            error!("不支持加密的 pdf 文件：{:?}", path);
+            return Err("不支持加密的 pdf 文件".to_string());
+        }
+
+        doc.renumber_objects_with(self.doc.max_id + 1);
+
+        let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+
+        // 页面脱离原来的页面树，需要保存从上级节点继承的属性
+        for &page_id in &page_ids {
+            let inherited = inherited_attributes(&doc, page_id);
+
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            for (key, value) in inherited {
+                if !page.has(key.as_bytes()) {
+                    page.set(key, value);
+                }
+            }
+
+            let rotate = page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0);
+            page.set(
+                "Rotate",
+                (rotate + rotation.quarter_turns() as i64 * 90).rem_euclid(360),
+            );
+            page.set("Parent", self.pages_id);
+        }
+
+        // 只复制页面直接或间接引用的对象
+        let mut imported = BTreeSet::new();
+        let mut pending = page_ids.clone();
+        while let Some(id) = pending.pop() {
+            if !imported.insert(id) {
+                continue;
+            }
+            if let Ok(object) = doc.get_object(id) {
+                collect_references(object, &mut pending);
+            }
+        }
+
+        for id in &imported {
+            if let Some(object) = doc.objects.remove(id) {
+                self.doc.objects.insert(*id, object);
+            }
+        }
+        self.doc.max_id = self.doc.max_id.max(doc.max_id);
+        if doc.version > self.doc.version {
+            self.doc.version = doc.version;
+        }
+
+        let removed = self.deduplicate_resources(&mut imported);
+        debug!(
+            "已导入 pdf 文件 {:?}，共 {} 页，{} 个对象，去除了 {} 个重复的资源",
+            path,
+            page_ids.len(),
+            imported.len(),
+            removed
+        );
+
+        Ok(page_ids)
+    }
+
+    /// 合并新导入的对象中与已有资源相同的对象，返回删除的对象数量。
+    ///
+    /// 合并后引用它的对象也可能变得相同，所以重复查找直到没有可以合并的对象。
+    fn deduplicate_resources(&mut self, imported: &mut BTreeSet<ObjectId>) -> usize {
+        let mut removed = 0;
+
+        loop {
+            let mut replaced = BTreeMap::new();
+
+            for &id in imported.iter() {
+                let Some(key) = self.doc.objects.get(&id).and_then(resource_key) else {
+                    continue;
+                };
+
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+                let digest = hasher.finish();
+
+                match self.resources.get(&digest) {
+                    Some(&existing) if existing != id => {
+                        let same = self.doc.objects.get(&existing).and_then(resource_key);
+                        if same.as_ref() == Some(&key) {
+                            replaced.insert(id, existing);
+                        } else {
+                            self.resources.insert(digest, id);
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        self.resources.insert(digest, id);
+                    }
+                }
+            }
+
+            if replaced.is_empty() {
+                return removed;
+            }
+
+            for id in replaced.keys() {
+                self.doc.objects.remove(id);
+                imported.remove(id);
+            }
+            removed += replaced.len();
+
+            for id in imported.iter() {
+                if let Some(object) = self.doc.objects.get_mut(id) {
+                    replace_references(object, &replaced);
+                }
+            }
+        }
+    }
+
+    /// 按页码重建页面树，页面树只保留一层，其他页面从文档中删除。
+    ///
+    /// 页码从 1 开始，可以重复，重复的页面会复制一份。
+    fn rebuild_page_tree(&mut self, numbers: &[usize]) -> std::result::Result<(), String> {
+        let pages = self.doc.get_pages();
+        let old_nodes: Vec<ObjectId> = pages
+            .values()
+            .flat_map(|page_id| page_tree_ancestors(&self.doc, *page_id))
+            .filter(|id| *id != self.pages_id)
+            .collect();
+
+        let mut kids = Vec::with_capacity(numbers.len());
+        let mut kept = BTreeSet::new();
+
+        for number in numbers {
+            let page_id = *pages.get(&(*number as u32)).ok_or_else(|| {
+                error!("页码超出范围：{}，共 {} 页", number, pages.len());
+                format!("页码超出范围：{}", number)
+            })?;
+
+            // 页面脱离原来的页面树，需要保存从上级节点继承的属性
+            let inherited = inherited_attributes(&self.doc, page_id);
+            let mut page = self
+                .doc
+                .get_dictionary(page_id)
+                .map_err(|e| e.to_string())?
+                .clone();
+            for (key, value) in inherited {
+                if !page.has(key.as_bytes()) {
+                    page.set(key, value);
+                }
+            }
+            page.set("Parent", self.pages_id);
+
+            if kept.insert(page_id) {
+                self.doc.objects.insert(page_id, Object::Dictionary(page));
+                kids.push(Object::from(page_id));
+            } else {
+                kids.push(Object::from(self.doc.add_object(page)));
+            }
+        }
+
+        let removed: BTreeSet<ObjectId> = pages
+            .values()
+            .filter(|id| !kept.contains(id))
+            .copied()
+            .collect();
+        remove_destinations(&mut self.doc, &removed);
+
+        // 结构树中的元素通过 /Pg 指向页面，删除页面后无法保持一致，所以直接删除结构树
+        if !removed.is_empty() {
+            if let Ok(catalog) = self.doc.catalog_mut() {
+                if catalog.remove(b"StructTreeRoot").is_some() {
+                    catalog.remove(b"MarkInfo");
+                    debug!("页面已删除，删除文档结构树");
+                }
+            }
+        }
+
+        for id in removed.iter().chain(&old_nodes) {
+            self.doc.objects.remove(id);
+        }
+
+        let root = self
+            .doc
+            .get_dictionary_mut(self.pages_id)
+            .map_err(|e| e.to_string())?;
+        root.set("Count", kids.len() as i64);
+        root.set("Kids", kids);
+
+        // 页面顺序改变后原来的页码标签不再正确
+        if numbers.iter().copied().ne(1..=pages.len()) {
+            if let Ok(catalog) = self.doc.catalog_mut() {
+                if catalog.remove(b"PageLabels").is_some() {
+                    debug!("页面已改变，删除原来的页码标签");
+                }
+            }
+        }
+
+        let pruned = self.doc.prune_objects();
+        debug!(
+            "已重建页面树，共 {} 页，删除了 {} 个页面和 {} 个不再使用的对象",
+            numbers.len(),
+            removed.len(),
+            pruned.len()
+        );
+
+        Ok(())
+    }
+
+    /// 复制拆分出的一段页面需要的对象。
+    ///
+    /// 其他页面只复制页面字典以保持页码不变，不复制其内容和资源，避免每一段都复制整个文档。
+    fn copy_for_pages(&self, numbers: &[usize]) -> Document {
+        let pages = self.doc.get_pages();
+        let kept: BTreeSet<ObjectId> = numbers
+            .iter()
+            .filter_map(|number| pages.get(&(*number as u32)))
+            .copied()
+            .collect();
+        let skipped: BTreeSet<ObjectId> = pages
+            .values()
+            .filter(|id| !kept.contains(id))
+            .copied()
+            .collect();
+
+        let mut doc = Document::with_version(self.doc.version.clone());
+        doc.trailer = self.doc.trailer.clone();
+        doc.max_id = self.doc.max_id;
+
+        let mut stack = Vec::new();
+        for (_, value) in self.doc.trailer.iter() {
+            collect_references(value, &mut stack);
+        }
+
+        while let Some(id) = stack.pop() {
+            if doc.objects.contains_key(&id) {
+                continue;
+            }
+            let Some(object) = self.doc.objects.get(&id) else {
+                continue;
+            };
+
+            if !skipped.contains(&id) {
+                collect_references(object, &mut stack);
+            }
+            doc.objects.insert(id, object.clone());
+        }
+        trace!("第 {:?} 页共复制了 {} 个对象", numbers, doc.objects.len());
+
+        doc
+    }
+
+    /// 根据页面类型、页面方向和图片计算当前页面的尺寸。
+    fn page_size_for(&self, image: &ImageObject) -> PageSize {
+        let page_size = match self.page_size {
+            Some(page_size) => page_size,
+            None => {
+                // 页面由与图片实际尺寸相同的单元格、间距和页边距组成，不需要考虑页面方向
+                let size = self.image_dpi(image).physical_size(&image.size);
+                let columns = self.layout.columns as f32;
+                let rows = self.layout.rows as f32;
+
+                return PageSize::from((
+                    size.width as f32 * columns
+                        + self.layout.gutter * (columns - 1.0)
+                        + self.margins.left
+                        + self.margins.right,
+                    size.height as f32 * rows
+                        + self.layout.gutter * (rows - 1.0)
+                        + self.margins.top
+                        + self.margins.bottom,
+                ));
+            }
+        };
+
+        match self.orientation {
+            Orientation::Portrait => page_size.portrait(),
+            Orientation::Landscape => page_size.landscape(),
+            Orientation::Auto => {
+                // 按整个网格的宽高比决定
+                if image.size.width * self.layout.columns > image.size.height * self.layout.rows {
+                    page_size.landscape()
+                } else {
+                    page_size.portrait()
+                }
+            }
+        }
+    }
+
+    /// 图片的分辨率，用户指定的分辨率优先。
+    fn image_dpi(&self, image: &ImageObject) -> Dpi {
+        self.dpi.or(image.dpi).unwrap_or(Dpi::from(DEFAULT_DPI))
+    }
+
+    fn add_blank_page(&mut self, page_size: &PageSize) -> ObjectId {
+        // 需要有一个空 content 占位
+        let content_id = self.doc.add_object(Stream::new(dictionary! {}, vec![]));
+
+        let page_id = self.doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => self.pages_id,
+            "MediaBox" => vec![0.0.into(), 0.0.into(), page_size.width.into(), page_size.height.into()],
+            "Contents" => content_id,
+        });
+
+        debug!("添加一个空页面 {:?}", page_id);
+
+        page_id
+    }
+
+    /// 页面去除页边距、页眉和页脚后的区域。
+    fn content_area(&self, page_size: &PageSize) -> std::result::Result<Area, String> {
+        // 页眉和页脚各占一行
+        let line_height = |page_text: &Option<PageText>| match page_text {
+            Some(page_text) => page_text.font_size * CAPTION_LINE_HEIGHT,
+            None => 0.0,
+        };
+        let header_height = line_height(&self.header);
+        let footer_height = line_height(&self.footer);
+
+        let area = Area {
+            x: self.margins.left,
+            y: self.margins.bottom + footer_height,
+            width: page_size.width as f32 - self.margins.left - self.margins.right,
+            height: page_size.height as f32
+                - self.margins.top
+                - self.margins.bottom
+                - header_height
+                - footer_height,
+        };
+
+        if area.width < 1.0 || area.height < 1.0 {
+            error!("页边距 {:?} 超出了页面尺寸 {:?}", self.margins, page_size);
+            return Err("页边距超出了页面尺寸".to_string());
+        }
+
+        let cell = area.cell(&self.layout, 0);
+        if cell.width < 1.0 || cell.height < 1.0 {
+            error!("网格布局 {:?} 超出了页面尺寸 {:?}", self.layout, page_size);
+            return Err("网格间距超出了页面尺寸".to_string());
+        }
+
+        Ok(area)
+    }
+
+    /// 按缩放方式计算图片在区域中的尺寸。
+    fn scale(&self, image: &ImageObject, area: &Area) -> ImageSize {
+        let area_size = ImageSize::from(area);
+
+        match self.fit_mode {
+            FitMode::Contain => scale(&image.size, &area_size),
+            FitMode::Cover => scale_to_cover(&image.size, &area_size),
+            FitMode::Stretch => area_size,
+            FitMode::NeverUpscale => {
+                // 与实际尺寸相同，按分辨率换算后再比较
+                let dpi = self.image_dpi(image);
+                debug!("按分辨率 {:?} 缩小图片", dpi);
+
+                scale_down(&dpi.physical_size(&image.size), &area_size)
+            }
+            FitMode::ActualSize => {
+                let dpi = self.image_dpi(image);
+                debug!("按分辨率 {:?} 放置图片", dpi);
+
+                dpi.physical_size(&image.size)
+            }
+        }
+    }
+
+    /// 超出区域的部分是否需要裁剪。
+    fn need_clip(&self) -> bool {
+        matches!(self.fit_mode, FitMode::Cover | FitMode::ActualSize)
+    }
+
+    /// 按用户为单张图片指定的尺寸或缩放比例调整图片尺寸。
+    fn resize(&self, image: &models::Image, scaled: ImageSize) -> ImageSize {
+        if let Some(size) = image.size {
+            return ImageSize::from(size);
+        }
+
+        match image.scale {
+            Some(percent) => ImageSize::from((
+                scaled.width as f32 * percent / 100.0,
+                scaled.height as f32 * percent / 100.0,
+            )),
+            None => scaled,
+        }
+    }
+
+    /// 嵌入 ICC 配置文件，返回 /ICCBased 颜色空间使用的流对象。
+    fn icc_profile_id(&mut self, profile: Vec<u8>, device: &[u8], components: i64) -> ObjectId {
+        if let Some(id) = self.icc_profiles.get(&profile) {
+            trace!("复用已嵌入的 ICC 配置文件 {:?}", id);
+            return *id;
+        }
+
+        let mut stream = Stream::new(
+            dictionary! {
+                "N" => components,
+                "Alternate" => Object::Name(device.to_vec()),
+            },
+            profile.clone(),
+        );
+        // Ignore any compression error.
+        let _ = stream.compress();
+
+        let id = self.doc.add_object(stream);
+        debug!("已嵌入 ICC 配置文件 {:?}", id);
+
+        self.icc_profiles.insert(profile, id);
+
+        id
+    }
+
+    /// 把图片的设备颜色空间替换为 ICC 配置文件对应的 /ICCBased 颜色空间。
+    ///
+    /// 调色板图片替换的是调色板的基础颜色空间。
+    fn apply_icc_profile(&mut self, dict: &mut Dictionary, profile: Vec<u8>) {
+        // ICC 配置文件头中第 16～19 字节是其颜色空间
+        let (device, components): (&[u8], i64) = match profile.get(16..20) {
+            Some(b"RGB ") => (b"DeviceRGB", 3),
+            Some(b"GRAY") => (b"DeviceGray", 1),
+            Some(b"CMYK") => (b"DeviceCMYK", 4),
+            other => {
+                warn!("不支持的 ICC 配置文件颜色空间：{:?}", other);
+                return;
+            }
+        };
+
+        let color_space = match dict.get_mut(b"ColorSpace") {
+            Ok(Object::Array(indexed)) if indexed.len() == 4 => &mut indexed[1],
+            Ok(color_space) => color_space,
+            Err(_) => return,
+        };
+
+        // 图片解码后可能已转换为其他颜色空间，此时配置文件不再适用
+        if color_space.as_name().ok() != Some(device) {
+            debug!(
+                "图片颜色空间 {:?} 与 ICC 配置文件不一致，不使用配置文件",
+                color_space
+            );
+            return;
+        }
+
+        let id = self.icc_profile_id(profile, device, components);
+        *color_space = Object::Array(vec![Object::Name(b"ICCBased".to_vec()), id.into()]);
+    }
+
+    /// 把图片及其透明通道写入 pdf，返回图片对象的编号。
+    fn add_image(
+        &mut self,
+        mut stream: Stream,
+        smask: Option<Stream>,
+        icc_profile: Option<Vec<u8>>,
+    ) -> ObjectId {
+        if let Some(profile) = icc_profile {
+            self.apply_icc_profile(&mut stream.dict, profile);
+        }
+
+        if let Some(smask) = smask {
+            let smask_id = self.doc.add_object(smask);
+            stream.dict.set("SMask", smask_id);
+        }
+
+        self.doc.add_object(stream)
+    }
+
+    /// 插入图片，clip 不为空时只显示图片在 clip 区域内的部分。
+    ///
+    /// size 是旋转后的图片尺寸。
+    fn insert_image(
+        &mut self,
+        page_id: ObjectId,
+        content: &mut ContentBuilder,
+        image: ImageObject,
+        position: Position,
+        size: ImageSize,
+        clip: Option<&Area>,
+    ) -> Result<()> {
+        let image_id = self.add_image(image.stream, image.smask, image.icc_profile);
+        let image_name = format!("X{}", image_id.0);
+
+        self.doc
+            .add_xobject(page_id, image_name.as_bytes(), image_id)?;
+
+        content.save_state();
+        if let Some(area) = clip {
+            content.clip(area);
+        }
+        content.transform(image.rotation.matrix(position, size, image.mirror));
+        content.draw_xobject(image_name.as_bytes());
+        content.restore_state();
+
+        Ok(())
+    }
+
+    /// 文字是否使用内嵌字体绘制，需要时才查找内嵌字体。
+    fn use_embedded_font(&mut self, text: &str) -> bool {
+        if helvetica_supports(text) {
+            return false;
+        }
+
+        if !self.font_loaded {
+            self.font_loaded = true;
+            self.embedded_font = EmbeddedFont::find(self.font_path.as_deref())
+                .map(|font| (self.doc.new_object_id(), font));
+        }
+
+        self.embedded_font.is_some()
+    }
+
+    /// 绘制文字时的宽度。
+    fn text_width(&mut self, text: &str, size: f32) -> f32 {
+        if !self.use_embedded_font(text) {
+            return helvetica_width(text, size);
+        }
+
+        match &self.embedded_font {
+            Some((_, font)) => font.width(text, size),
+            None => helvetica_width(text, size),
+        }
+    }
+
+    /// 文字使用的字体对象和编码后的字符串。
+    ///
+    /// ASCII 文字使用 Helvetica，其他文字使用内嵌字体，没有可用的内嵌字体时不支持的字符显示为 `?`。
+    fn encode_text(&mut self, text: &str) -> (ObjectId, Object) {
+        if self.use_embedded_font(text) {
+            if let Some((font_id, font)) = &mut self.embedded_font {
+                return (
+                    *font_id,
+                    Object::String(font.encode(text), StringFormat::Hexadecimal),
+                );
+            }
+        }
+
+        let font_id = match self.helvetica_id {
+            Some(id) => id,
+            None => {
+                let id = self.doc.add_object(helvetica());
+                self.helvetica_id = Some(id);
+                id
+            }
+        };
+
+        let bytes: Vec<u8> = text
+            .chars()
+            .map(|c| {
+                if helvetica_supports(&c.to_string()) {
+                    c as u8
+                } else {
+                    b'?'
+                }
+            })
+            .collect();
+
+        (font_id, Object::string_literal(bytes))
+    }
+
+    /// 把字体添加到页面资源中。
+    fn add_font(&mut self, page_id: ObjectId, name: &[u8], font_id: ObjectId) -> Result<()> {
+        let resources = self
+            .doc
+            .get_or_create_resources(page_id)
+            .and_then(Object::as_dict_mut)?;
+
+        if !resources.has(b"Font") {
+            resources.set("Font", Dictionary::new());
+        }
+
+        resources
+            .get_mut(b"Font")
+            .and_then(Object::as_dict_mut)?
+            .set(name, font_id);
+
+        Ok(())
+    }
+
+    /// 在页面中绘制一行文字，position 是文字基线的起点。
+    fn insert_text(
+        &mut self,
+        page_id: ObjectId,
+        content: &mut ContentBuilder,
+        text: &str,
+        position: Position,
+        size: f32,
+        color: [u8; 3],
+    ) -> Result<()> {
+        let (font_id, string) = self.encode_text(text);
+        let font_name = format!("F{}", font_id.0);
+
+        self.add_font(page_id, font_name.as_bytes(), font_id)?;
+
+        content.save_state();
+        content.fill_color(color);
+        content.text(font_name.as_bytes(), size, position, string);
+        content.restore_state();
+
+        Ok(())
+    }
+
+    /// 按模板在页面的页眉和页脚位置绘制文字，number 从 1 开始。
+    fn insert_page_texts(
+        &mut self,
+        page: &mut Page,
+        number: usize,
+        total: usize,
+        date: &str,
+    ) -> Result<()> {
+        for (page_text, is_header) in [(self.header.clone(), true), (self.footer.clone(), false)] {
+            let page_text = match page_text {
+                Some(page_text) => page_text,
+                None => continue,
+            };
+
+            let text = page_text
+                .template
+                .replace("{page}", &number.to_string())
+                .replace("{total}", &total.to_string())
+                .replace("{filename}", &page.file_name)
+                .replace("{date}", date);
+            if text.is_empty() {
+                continue;
+            }
+
+            let size = page_text.font_size;
+            let width = self.text_width(&text, size);
+
+            let x = match page_text.align {
+                TextAlign::Left => self.margins.left,
+                TextAlign::Center => {
+                    let content_width =
+                        page.size.width as f32 - self.margins.left - self.margins.right;
+                    self.margins.left + (content_width - width) / 2.0
+                }
+                TextAlign::Right => page.size.width as f32 - self.margins.right - width,
+            };
+
+            // 页眉在上边距下方，页脚在下边距上方，与图片之间留有间距
+            let y = if is_header {
+                page.size.height as f32 - self.margins.top - size
+            } else {
+                self.margins.bottom + size * (CAPTION_LINE_HEIGHT - 1.0)
+            };
+            trace!("页面 {:?} 的页眉或页脚 {:?}", page.id, text);
+
+            self.insert_text(
+                page.id,
+                &mut page.content,
+                &text,
+                Position::from((x, y)),
+                size,
+                page_text.color,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// 把水印的字体或图片以及不透明度写入 pdf，图片水印与普通图片使用相同的处理方式。
+    async fn prepare_watermark(
+        &mut self,
+        watermark: &Watermark,
+        options: EmbedOptions,
+    ) -> std::result::Result<(), String> {
+        if watermark.gap < 0.0 || !watermark.gap.is_finite() {
+            error!("水印间距不能小于 0：{}", watermark.gap);
+            return Err("水印间距不能小于 0".to_string());
+        }
+
+        let object = match &watermark.content {
+            WatermarkContent::Text(text) => {
+                if text.is_empty() {
+                    return Ok(());
+                }
+
+                if watermark.font_size <= 0.0 || !watermark.font_size.is_finite() {
+                    error!("文字水印的字号必需大于 0：{}", watermark.font_size);
+                    return Err("文字水印的字号必需大于 0".to_string());
+                }
+
+                let width = self.text_width(text, watermark.font_size);
+                let (font_id, string) = self.encode_text(text);
+
+                WatermarkObject::Text {
+                    font_id,
+                    string,
+                    width,
+                }
+            }
+            WatermarkContent::Image(path) => {
+                if let Some(width) = watermark
+                    .width
+                    .filter(|width| *width <= 0.0 || !width.is_finite())
+                {
+                    error!("水印图片的宽度必需大于 0：{}", width);
+                    return Err("水印图片的宽度必需大于 0".to_string());
+                }
+
+                let image = ImageObject::new(path.clone(), options)
+                    .await
+                    .map_err(|err| {
+                        error!("读取水印图片时出错：{}", err);
+                        err.to_string()
+                    })?
+                    .into_iter()
+                    .next()
+                    .ok_or("水印图片为空".to_string())?;
+
+                let physical_size = self.image_dpi(&image).physical_size(&image.size);
+                let size = match watermark.width {
+                    Some(width) => ImageSize::from((
+                        width,
+                        width * image.size.height as f32 / image.size.width as f32,
+                    )),
+                    None => physical_size,
+                };
+
+                WatermarkObject::Image {
+                    id: self.add_image(image.stream, image.smask, image.icc_profile),
+                    size,
+                    rotation: image.rotation,
+                    mirror: image.mirror,
+                }
+            }
+        };
+
+        let opacity = watermark.opacity.clamp(0.0, 1.0);
+        let gs_id = self.doc.add_object(dictionary! {
+            "Type" => "ExtGState",
+            "ca" => opacity,
+            "CA" => opacity,
+        });
+        debug!("已准备水印 {:?}，不透明度 {}", watermark.content, opacity);
+
+        self.watermark = Some(PreparedWatermark {
+            options: watermark.clone(),
+            gs_id,
+            object,
+        });
+
+        Ok(())
+    }
+
+    /// 在页面中绘制水印，above 表示当前绘制的是图片上方还是下方的水印。
+    fn insert_watermark(&mut self, page: &mut Page, above: bool) -> Result<()> {
+        let watermark = match &self.watermark {
+            Some(watermark) if watermark.options.above == above => watermark,
+            _ => return Ok(()),
+        };
+
+        let gs_name = format!("GS{}", watermark.gs_id.0);
+        let (width, height) = match &watermark.object {
+            WatermarkObject::Text { width, .. } => (*width, watermark.options.font_size),
+            WatermarkObject::Image { size, .. } => (size.width as f32, size.height as f32),
+        };
+
+        let angle = watermark.options.rotate.to_radians();
+        let (sin, cos) = angle.sin_cos();
+
+        let (page_width, page_height) = (page.size.width as f32, page.size.height as f32);
+
+        // 很小的水印平铺后数量巨大，间隔不能小于下限
+        let mut step_x = ((width * cos).abs() + (height * sin).abs() + watermark.options.gap)
+            .max(MIN_WATERMARK_STEP);
+        let mut step_y = ((width * sin).abs() + (height * cos).abs() + watermark.options.gap)
+            .max(MIN_WATERMARK_STEP);
+        if watermark.options.tiled {
+            // 超出数量上限时按比例放大间隔
+            let tiles = (page_width / step_x + 1.0) * (page_height / step_y + 1.0);
+            if tiles > MAX_WATERMARK_TILES {
+                let factor = (tiles / MAX_WATERMARK_TILES).sqrt();
+                warn!(
+                    "页面 {:?} 需要平铺约 {} 个水印，超出上限 {}，间隔放大 {} 倍",
+                    page.id, tiles as u64, MAX_WATERMARK_TILES, factor
+                );
+                step_x *= factor;
+                step_y *= factor;
+            }
+        }
+
+        // 水印中心的坐标，平铺时按旋转后的外接矩形排列，每隔一行错开半个水印
+        let centers = if watermark.options.tiled {
+            let mut centers = Vec::new();
+            let mut row = 0;
+            let mut y = step_y / 2.0;
+            while y - step_y / 2.0 < page_height {
+                let mut x = if row % 2 == 0 { step_x / 2.0 } else { 0.0 };
+                while x - step_x / 2.0 < page_width {
+                    centers.push((x, y));
+                    x += step_x;
+                }
+                y += step_y;
+                row += 1;
+            }
+
+            centers
+        } else {
+            vec![(page_width / 2.0, page_height / 2.0)]
+        };
+        trace!("页面 {:?} 中有 {} 个水印", page.id, centers.len());
+
+        let content = &mut page.content;
+        content.save_state();
+        content.graphics_state(gs_name.as_bytes());
+
+        let font_name = match &watermark.object {
+            WatermarkObject::Text { font_id, .. } => {
+                content.fill_color(watermark.options.color);
+                format!("F{}", font_id.0)
+            }
+            WatermarkObject::Image { id, .. } => format!("X{}", id.0),
+        };
+
+        for (x, y) in centers {
+            content.save_state();
+            // 以水印中心为原点旋转
+            content.transform([cos, sin, -sin, cos, x, y]);
+
+            match &watermark.object {
+                WatermarkObject::Text { string, .. } => {
+                    // 文字的视觉中心大约在基线上方 0.35 倍字号处
+                    content.text(
+                        font_name.as_bytes(),
+                        height,
+                        Position::from((-width / 2.0, -height * 0.35)),
+                        string.clone(),
+                    );
+                }
+                WatermarkObject::Image {
+                    size,
+                    rotation,
+                    mirror,
+                    ..
+                } => {
+                    let position = Position::from((-width / 2.0, -height / 2.0));
+                    content.transform(rotation.matrix(
+                        position,
+                        ImageSize::from((size.width, size.height)),
+                        *mirror,
+                    ));
+                    content.draw_xobject(font_name.as_bytes());
+                }
+            }
+
+            content.restore_state();
+        }
+
+        content.restore_state();
+
+        let gs_id = watermark.gs_id;
+        match &watermark.object {
+            WatermarkObject::Text { font_id, .. } => {
+                let font_id = *font_id;
+                self.add_font(page.id, font_name.as_bytes(), font_id)?;
+            }
+            WatermarkObject::Image { id, .. } => {
+                let id = *id;
+                self.doc.add_xobject(page.id, font_name.as_bytes(), id)?;
+            }
+        }
+        self.doc
+            .add_graphics_state(page.id, gs_name.as_bytes(), gs_id)
+    }
+
+    /// 把绘制操作写入页面的内容流。
+    fn write_content(&mut self, page_id: ObjectId, content: ContentBuilder) -> Result<()> {
+        self.doc
+            .change_page_content(page_id, content.build().encode()?)
+    }
+
+    /// 把内嵌字体用到的字形子集写入 pdf。
+    fn embed_fonts(&mut self) -> std::result::Result<(), String> {
+        if let Some((font_id, font)) = &self.embedded_font {
+            font.write(&mut self.doc, *font_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn insert_pages(&mut self, pages: Dictionary) {
+        self.doc
+            .objects
+            .insert(self.pages_id, Object::Dictionary(pages));
+
+        trace!("添加所有页面");
+    }
+
+    /// 记录图片的第一页，用于生成书签。
+    fn add_bookmark(&mut self, path: &Path, page_id: ObjectId) {
+        if self.outline_mode != OutlineMode::None {
+            self.bookmarks.push((path.to_path_buf(), page_id));
+        }
+    }
+
+    /// 按书签方式把图片书签组织成树。
+    fn outline_tree(&self) -> Vec<OutlineNode> {
+        let file_name = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+
+        let items = self.bookmarks.iter().map(|(path, page_id)| OutlineNode {
+            title: file_name(path),
+            page_id: *page_id,
+            children: Vec::new(),
+        });
+
+        if self.outline_mode != OutlineMode::Folders {
+            return items.collect();
+        }
+
+        // 所有图片共同的上级目录，书签树从这一层目录开始
+        let common = self
+            .bookmarks
+            .iter()
+            .map(|(path, _)| path.parent().unwrap_or(Path::new("")))
+            .reduce(|common, folder| {
+                common
+                    .ancestors()
+                    .find(|ancestor| folder.starts_with(ancestor))
+                    .unwrap_or(Path::new(""))
+            })
+            .unwrap_or(Path::new(""));
+        let base = common.parent().unwrap_or(Path::new(""));
+
+        // 路径中的每一层目录都是一个书签，同一目录中不相邻的图片也放在同一个目录书签下
+        let mut roots: Vec<OutlineNode> = Vec::new();
+        for ((path, _), item) in self.bookmarks.iter().zip(items) {
+            let folder = path.parent().unwrap_or(Path::new(""));
+            let mut nodes = &mut roots;
+
+            for component in folder.strip_prefix(base).unwrap_or(folder).components() {
+                let title = component.as_os_str().to_string_lossy().to_string();
+                // 目录书签至少包含一个子书签，以此与图片书签区分
+                let index = match nodes
+                    .iter()
+                    .position(|node| !node.children.is_empty() && node.title == title)
+                {
+                    Some(index) => index,
+                    None => {
+                        nodes.push(OutlineNode {
+                            title,
+                            // 目录书签指向其中第一张图片
+                            page_id: item.page_id,
+                            children: Vec::new(),
+                        });
+                        nodes.len() - 1
+                    }
+                };
+
+                nodes = &mut nodes[index].children;
+            }
+
+            nodes.push(item);
+        }
+
+        roots
+    }
+
+    /// 把同一层级的书签写入 pdf，返回第一个和最后一个书签以及所有展开的书签数量。
+    fn add_outline_items(
+        &mut self,
+        parent_id: ObjectId,
+        nodes: Vec<OutlineNode>,
+    ) -> (ObjectId, ObjectId, i64) {
+        let ids: Vec<ObjectId> = nodes.iter().map(|_| self.doc.new_object_id()).collect();
+        let mut count = ids.len() as i64;
+
+        for (i, node) in nodes.into_iter().enumerate() {
+            let mut item = dictionary! {
+                "Title" => text_string(&node.title),
+                "Parent" => parent_id,
+                "Dest" => vec![node.page_id.into(), "Fit".into()],
+            };
+
+            if i > 0 {
+                item.set("Prev", ids[i - 1]);
+            }
+            if let Some(next) = ids.get(i + 1) {
+                item.set("Next", *next);
+            }
+
+            if !node.children.is_empty() {
+                let (first, last, children) = self.add_outline_items(ids[i], node.children);
+                item.set("First", first);
+                item.set("Last", last);
+                // 正数表示书签默认展开
+                item.set("Count", children);
+                count += children;
+            }
+
+            self.doc.objects.insert(ids[i], Object::Dictionary(item));
+        }
+
+        (ids[0], ids[ids.len() - 1], count)
+    }
+
+    /// 创建书签，不需要书签时返回 `None`。
+    fn create_outlines(&mut self) -> Option<ObjectId> {
+        let tree = self.outline_tree();
+        if tree.is_empty() {
+            return None;
+        }
+
+        let outlines_id = self.doc.new_object_id();
+        let (first, last, count) = self.add_outline_items(outlines_id, tree);
+
+        self.doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => first,
+                "Last" => last,
+                "Count" => count,
+            }),
+        );
+        debug!("已创建书签 {:?}，共 {} 项", outlines_id, count);
+
+        Some(outlines_id)
+    }
+
+    /// 创建页码标签数字树，没有设置页码标签时返回 `None`。
+    fn create_page_labels(&self) -> Option<Dictionary> {
+        if self.page_labels.is_empty() {
+            return None;
+        }
+
+        // 目录还没有创建，不能使用 get_pages
+        let total = self
+            .doc
+            .get_dictionary(self.pages_id)
+            .and_then(|pages| pages.get(b"Count"))
+            .and_then(Object::as_i64)
+            .unwrap_or(0) as usize;
+
+        let mut labels: Vec<&PageLabel> = self
+            .page_labels
+            .iter()
+            .filter(|label| {
+                let valid = label.start >= 1 && label.start <= total;
+                if !valid {
+                    warn!("页码标签的开始页面超出范围，已忽略：{:?}", label);
+                }
+                valid
+            })
+            .collect();
+        // 数字树中的键必需按升序排列，相同开始页面的使用后面的标签
+        labels.reverse();
+        labels.sort_by_key(|label| label.start);
+        labels.dedup_by_key(|label| label.start);
+
+        let mut nums: Vec<Object> = Vec::with_capacity(labels.len() * 2 + 2);
+        // 数字树必需包含第一页
+        if labels.first().map(|label| label.start) != Some(1) {
+            nums.push(Object::Integer(0));
+            nums.push(PageLabel::default().to_dictionary().into());
+        }
+        for label in labels {
+            nums.push(Object::Integer(label.start as i64 - 1));
+            nums.push(label.to_dictionary().into());
+        }
+        debug!("已创建页码标签，共 {} 段", nums.len() / 2);
+
+        Some(dictionary! {
+            "Nums" => nums,
+        })
+    }
+
+    fn create_catalog(&mut self) {
+        let now = now();
+
+        let info_id = self.doc.add_object(self.metadata.info(&now, &now));
+        self.doc.trailer.set("Info", info_id);
+        debug!("已添加文档信息 {:?}", info_id);
+
+        let metadata_id = self.doc.add_object(self.metadata.xmp(&now, &now));
+        trace!("已添加 XMP 元数据 {:?}", metadata_id);
+
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => self.pages_id,
+            "Metadata" => metadata_id,
+        };
+
+        if let Some(outlines_id) = self.create_outlines() {
+            catalog.set("Outlines", outlines_id);
+            // 打开文档时显示书签
+            catalog.set("PageMode", "UseOutlines");
+        }
+
+        if let Some(page_labels) = self.create_page_labels() {
+            catalog.set("PageLabels", page_labels);
+        }
+
+        let catalog_id = self.doc.add_object(catalog);
+        trace!("已创建目录 {:?}", catalog_id);
+
+        self.doc.trailer.set("Root", catalog_id);
+        debug!("已添加目录 {:?}", catalog_id);
+
+        self.doc.compress();
+        trace!("文档已压缩");
+    }
+
+    fn save(&mut self, output: PathBuf) -> std::io::Result<()> {
+        match self.doc.save(&output) {
+            Ok(_) => {
+                info!("已保存 pdf 文件：{:?}", output);
+                Ok(())
+            }
+            Err(e) => {
+                error!("保存文件时出错：{}", e);
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// 页面从页面树上级节点继承的属性。
+fn inherited_attributes(doc: &Document, page_id: ObjectId) -> Vec<(&'static str, Object)> {
+    let mut attributes: Vec<(&str, Object)> = Vec::new();
+    let mut visited = vec![page_id];
+
+    let mut node = doc.get_dictionary(page_id).ok();
+    while let Some(parent_id) =
+        node.and_then(|n| n.get(b"Parent").and_then(Object::as_reference).ok())
+    {
+        if visited.contains(&parent_id) {
+            break;
+        }
+        visited.push(parent_id);
+
+        node = doc.get_dictionary(parent_id).ok();
+        if let Some(parent) = node {
+            for key in ["Resources", "MediaBox", "CropBox", "Rotate"] {
+                // 离页面最近的节点优先
+                if attributes.iter().all(|(k, _)| *k != key) {
+                    if let Ok(value) = parent.get(key.as_bytes()) {
+                        attributes.push((key, value.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    attributes
+}
+
+/// 读取 pdf 文件的第一页用于缩略图，返回第一页中的第一张 jpeg 图片、页面尺寸和页数。
+pub fn read_pdf_preview(
+    buffer: &[u8],
+) -> std::result::Result<(Option<DynamicImage>, ImageSize, usize), String> {
+    let doc = Document::load_mem(buffer).map_err(|e| {
+        error!("读取 pdf 文件时出错：{}", e);
+        e.to_string()
+    })?;
+
+    let pages = doc.get_pages();
+    let page_id = *pages.values().next().ok_or_else(|| {
+        error!("pdf 文件中没有页面");
+        "pdf 文件中没有页面".to_string()
+    })?;
+
+    let mut page_size = ImageSize::from((595, 842));
+    let media_box = doc
+        .get_dictionary(page_id)
+        .and_then(|page| page.get(b"MediaBox"))
+        .ok()
+        .cloned()
+        .or_else(|| {
+            inherited_attributes(&doc, page_id)
+                .into_iter()
+                .find_map(|(key, value)| (key == "MediaBox").then_some(value))
+        });
+    if let Some(Ok(rect)) = media_box.as_ref().map(Object::as_array) {
+        let values: Vec<f32> = rect.iter().filter_map(|v| v.as_float().ok()).collect();
+        if let [x1, y1, x2, y2] = values[..] {
+            page_size = ImageSize::from(((x2 - x1).abs(), (y2 - y1).abs()));
+        }
+    }
+
+    let (resources, inherited) = doc.get_page_resources(page_id);
+    let image = resources
+        .into_iter()
+        .chain(
+            inherited
+                .into_iter()
+                .filter_map(|id| doc.get_dictionary(id).ok()),
+        )
+        .filter_map(|resources| resources.get(b"XObject").ok())
+        .filter_map(|xobjects| doc.dereference(xobjects).ok())
+        .filter_map(|(_, xobjects)| xobjects.as_dict().ok())
+        .flat_map(|xobjects| xobjects.iter())
+        .filter_map(|(_, xobject)| doc.dereference(xobject).ok())
+        .filter_map(|(_, xobject)| xobject.as_stream().ok())
+        .find(|stream| {
+            stream
+                .dict
+                .get(b"Subtype")
+                .and_then(Object::as_name_str)
+                .ok()
+                == Some("Image")
+                && stream
+                    .filters()
+                    .map(|filters| filters == ["DCTDecode"])
+                    .unwrap_or(false)
+        })
+        .and_then(|stream| {
+            image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg).ok()
+        });
+
+    Ok((image, page_size, pages.len()))
+}
+
+/// 页面在页面树中的所有上级节点。
+fn page_tree_ancestors(doc: &Document, page_id: ObjectId) -> Vec<ObjectId> {
+    let mut ancestors = Vec::new();
+
+    let mut node_id = page_id;
+    while let Ok(parent_id) = doc
+        .get_dictionary(node_id)
+        .and_then(|node| node.get(b"Parent"))
+        .and_then(Object::as_reference)
+    {
+        if parent_id == page_id || ancestors.contains(&parent_id) {
+            break;
+        }
+        ancestors.push(parent_id);
+        node_id = parent_id;
+    }
+
+    ancestors
+}
+
+/// 删除指向已删除页面的链接、书签、打开动作和命名目标。
+fn remove_destinations(doc: &mut Document, removed: &BTreeSet<ObjectId>) {
+    // 间接对象形式的目标数组，以及 /D 指向已删除页面的动作或命名目标字典
+    let mut dead: BTreeSet<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| matches!(object, Object::Array(_)))
+        .filter(|(_, object)| points_to_removed(object, removed, &BTreeSet::new()))
+        .map(|(id, _)| *id)
+        .collect();
+    let dead_dicts: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| matches!(object, Object::Dictionary(_)))
+        .filter(|(_, object)| points_to_removed(object, removed, &dead))
+        .map(|(id, _)| *id)
+        .collect();
+    dead.extend(dead_dicts);
+
+    // 目录中的 /Dests 字典，名称直接对应目标
+    let dests_id = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Dests").and_then(Object::as_reference).ok());
+
+    for (id, object) in doc.objects.iter_mut() {
+        prune_destinations(object, Some(*id) == dests_id, removed, &dead);
+    }
+}
+
+/// 目标是否指向已删除的页面。
+///
+/// 目标是以页面引用开头的数组，/GoTo 动作和命名目标也可能是包含 /D 的字典，dead 是已确定指向已删除页面的间接对象。
+fn points_to_removed(
+    dest: &Object,
+    removed: &BTreeSet<ObjectId>,
+    dead: &BTreeSet<ObjectId>,
+) -> bool {
+    match dest {
+        Object::Reference(id) => dead.contains(id),
+        Object::Array(dest) => dest
+            .first()
+            .and_then(|page| page.as_reference().ok())
+            .is_some_and(|page_id| removed.contains(&page_id)),
+        Object::Dictionary(dict) => dict
+            .get(b"D")
+            .is_ok_and(|dest| points_to_removed(dest, removed, dead)),
+        _ => false,
+    }
+}
+
+/// 删除对象及其内嵌字典中指向已删除页面的目标。
+///
+/// dest_map 表示对象是目录中的 /Dests 字典。
+fn prune_destinations(
+    object: &mut Object,
+    dest_map: bool,
+    removed: &BTreeSet<ObjectId>,
+    dead: &BTreeSet<ObjectId>,
+) {
+    let dict = match object {
+        Object::Dictionary(dict) => dict,
+        Object::Array(array) => {
+            for item in array.iter_mut() {
+                prune_destinations(item, false, removed, dead);
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    let points = |dest: &Object| points_to_removed(dest, removed, dead);
+
+    let keys: Vec<Vec<u8>> = dict
+        .iter()
+        .filter(|(key, value)| {
+            let is_dest = dest_map || [&b"Dest"[..], b"A", b"OpenAction"].contains(&key.as_slice());
+            is_dest && points(value)
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    // 名称树的节点中名称和目标交替排列
+    if let Ok(Object::Array(names)) = dict.get_mut(b"Names") {
+        if names.len() % 2 == 0 {
+            *names = names
+                .chunks(2)
+                .filter(|pair| !points(&pair[1]))
+                .flatten()
+                .cloned()
+                .collect();
+        }
+    }
+
+    for key in keys {
+        dict.remove(&key);
+    }
+
+    for (key, value) in dict.iter_mut() {
+        // 内嵌在目录中的 /Dests 字典，名称树的根节点有 /Kids 或 /Names
+        let dest_map = key == b"Dests"
+            && value
+                .as_dict()
+                .is_ok_and(|dests| !dests.has(b"Kids") && !dests.has(b"Names"));
+        prune_destinations(value, dest_map, removed, dead);
+    }
+}
+
+/// 解析页码范围，如 "1-3,5,8-"，每一段返回从 1 开始的页码。
+///
+/// "8-" 表示第 8 页到最后一页，"-3" 表示第 1 页到第 3 页。
+pub fn parse_page_ranges(
+    ranges: &str,
+    total: usize,
+) -> std::result::Result<Vec<Vec<usize>>, String> {
+    let parse = |value: &str, default: usize| -> std::result::Result<usize, String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(default);
+        }
+
+        value.parse::<usize>().map_err(|e| {
+            error!("无法解析页码 {:?}：{}", value, e);
+            format!("无法解析页码：{}", value)
+        })
+    };
+
+    let mut segments = Vec::new();
+
+    for range in ranges.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse(start, 1)?, parse(end, total)?),
+            None => {
+                let number = parse(range, 0)?;
+                (number, number)
+            }
+        };
+
+        if start == 0 || start > end || end > total {
+            error!("无效的页码范围：{}，共 {} 页", range, total);
+            return Err(format!("无效的页码范围：{}", range));
+        }
+
+        segments.push((start..=end).collect());
+    }
+
+    if segments.is_empty() {
+        error!("没有指定页码：{:?}", ranges);
+        return Err("没有指定页码".to_string());
+    }
+
+    Ok(segments)
+}
+
+/// 收集对象中引用的其他对象。
+fn collect_references(object: &Object, references: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => references.push(*id),
+        Object::Array(array) => array
+            .iter()
+            .for_each(|item| collect_references(item, references)),
+        Object::Dictionary(dict) => dict
+            .iter()
+            .for_each(|(_, value)| collect_references(value, references)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter()
+            .for_each(|(_, value)| collect_references(value, references)),
+        _ => {}
+    }
+}
+
+/// 把对象中的引用替换为合并后的对象。
+fn replace_references(object: &mut Object, replaced: &BTreeMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(new_id) = replaced.get(id) {
+                *id = *new_id;
+            }
+        }
+        Object::Array(array) => array
+            .iter_mut()
+            .for_each(|item| replace_references(item, replaced)),
+        Object::Dictionary(dict) => dict
+            .iter_mut()
+            .for_each(|(_, value)| replace_references(value, replaced)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter_mut()
+            .for_each(|(_, value)| replace_references(value, replaced)),
+        _ => {}
+    }
+}
+
+/// 可以在页面之间共享的资源对象的内容，其他对象返回 `None`。
+fn resource_key(object: &Object) -> Option<Vec<u8>> {
+    match object {
+        Object::Stream(stream) => {
+            let mut key = format!("{:?}", stream.dict).into_bytes();
+            key.extend_from_slice(&stream.content);
+            Some(key)
+        }
+        Object::Dictionary(dict) => match dict.get(b"Type").and_then(Object::as_name_str) {
+            Ok("Font" | "FontDescriptor" | "ExtGState") => Some(format!("{:?}", dict).into_bytes()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// 当前日期，格式为 YYYY-MM-DD。
+fn today() -> String {
+    now()
+        .format(format_description!("[year]-[month]-[day]"))
+        .unwrap_or_default()
+}
+
+/// 把图片逐页写入 pdf，返回按顺序排列的新页面。
+///
+/// offset 是第一个新页面在文档中的位置，existing 是文档中已有的页数，用于页眉和页脚中的页码。
+async fn embed_images(
+    pdf: &mut PDF,
+    images: &[models::Image],
+    options: &models::MergeOptions,
+    offset: usize,
+    existing: usize,
+) -> std::result::Result<Vec<ObjectId>, String> {
+    if options.layout.rows == 0 || options.layout.columns == 0 {
+        error!("网格布局的行数和列数必需大于 0：{:?}", options.layout);
+        return Err("网格布局的行数和列数必需大于 0".to_string());
+    }
+
+    if let Some(dpi) = options.dpi {
+        if dpi <= 0.0 || !dpi.is_finite() {
+            error!("分辨率必需大于 0：{}", dpi);
+            return Err("分辨率必需大于 0".to_string());
+        }
+    }
+
+    let font_size = options.captions.font_size;
+    if font_size <= 0.0 || !font_size.is_finite() {
+        error!("说明文字的字号必需大于 0：{}", font_size);
+        return Err("说明文字的字号必需大于 0".to_string());
+    }
+
+    for page_text in options.header.iter().chain(&options.footer) {
+        if page_text.font_size <= 0.0 || !page_text.font_size.is_finite() {
+            error!("页眉和页脚的字号必需大于 0：{:?}", page_text);
+            return Err("页眉和页脚的字号必需大于 0".to_string());
+        }
+    }
+
+    // /St 必需是正整数
+    if let Some(label) = options.page_labels.iter().find(|label| label.first < 1) {
+        error!("页码标签的起始编号必需大于 0：{:?}", label);
+        return Err("页码标签的起始编号必需大于 0".to_string());
+    }
+
+    if let Some(watermark) = &options.watermark {
+        pdf.prepare_watermark(watermark, EmbedOptions::from(options))
+            .await?;
+    }
+
+    let mut tasks = Vec::with_capacity(images.len());
+
+    for image in images.iter() {
+        let rotation = Rotation::try_from(image.rotate)?;
+        // pdf 文件的页面直接复制，不需要处理
+        let task = if is_pdf(&image.path) {
+            None
+        } else {
+            let path = image.path.clone();
+            Some(tokio::spawn(ImageObject::new(
+                path,
+                EmbedOptions::from(options),
+            )))
+        };
+        tasks.push((image, rotation, task));
+    }
+
+    let mut pages: Vec<Page> = Vec::with_capacity(images.len());
+    // 从 pdf 文件复制的页面，以及它们之前的图片页面数量
+    let mut imported: Vec<(usize, Vec<ObjectId>)> = Vec::new();
+
+    let cells = pdf.layout.cells();
+    // 当前页面的可用区域
+    let mut area: Option<Area> = None;
+    // 下一张图片在当前页面中的单元格
+    let mut index = 0;
+
+    // 页面方向可能取决于图片尺寸，所以页面在图片处理完成后按顺序创建。
+    for (item, rotation, task) in tasks {
+        let Some(task) = task else {
+            let page_ids = pdf.import_pdf(&item.path, rotation)?;
+            if let Some(page_id) = page_ids.first() {
+                pdf.add_bookmark(&item.path, *page_id);
+            }
+            imported.push((pages.len(), page_ids));
+
+            // 之后的图片从新页面开始
+            area = None;
+            continue;
+        };
+
+        let images = task
+            .await
+            .map_err(|err| {
+                error!("并发 join 时出错: {}", err);
+
+                return err.to_string();
+            })?
+            .map_err(|e| e.to_string())?;
+
+        let file_name = item
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        // 多页 tiff 的每一页都使用同一个图片项的设置
+        for (frame, mut image) in images.into_iter().enumerate() {
+            // 后续的布局都基于旋转后的尺寸
+            image.rotate(rotation);
+
+            // 当前页面的单元格用完后创建新页面，页面尺寸取决于其第一张图片
+            let page_area = match area {
+                Some(area) if index < cells => area,
+                _ => {
+                    let page_size = pdf.page_size_for(&image);
+                    let page_id = pdf.add_blank_page(&page_size);
+                    debug!("页面 {:?} 尺寸 {:?}", page_id, page_size);
+
+                    let page_area = pdf.content_area(&page_size)?;
+
+                    let mut page = Page {
+                        id: page_id,
+                        size: page_size,
+                        file_name: file_name.clone(),
+                        content: ContentBuilder::default(),
+                    };
+                    pdf.insert_watermark(&mut page, false)
+                        .map_err(|err| err.to_string())?;
+
+                    pages.push(page);
+                    area = Some(page_area);
+                    index = 0;
+
+                    page_area
+                }
+            };
+            // 上面已确保至少有一个页面
+            let page = pages.last_mut().unwrap();
+
+            if frame == 0 {
+                pdf.add_bookmark(&item.path, page.id);
+            }
+
+            let cell = page_area.cell(&pdf.layout, index);
+            index += 1;
+
+            let caption = item.caption.clone().or_else(|| {
+                if options.captions.file_name {
+                    Some(file_name.clone())
+                } else {
+                    None
+                }
+            });
+
+            // 说明文字占用单元格底部一行，文字超出单元格宽度时缩小字号
+            let caption = caption.map(|text| {
+                let width = pdf.text_width(&text, options.captions.font_size);
+                let size = if width > cell.width {
+                    options.captions.font_size * cell.width / width
+                } else {
+                    options.captions.font_size
+                };
+
+                (text, size, width * size / options.captions.font_size)
+            });
+            let cell = match &caption {
+                Some(_) => cell.above(options.captions.font_size * CAPTION_LINE_HEIGHT),
+                None => cell,
+            };
+
+            let scaled = pdf.resize(item, pdf.scale(&image, &cell));
+            debug!("图片缩放尺寸 {:?} -> {:?}", image.size, scaled);
+
+            let position = match item.position {
+                Some(position) => Position::from(position),
+                None => cell.align(&scaled, pdf.alignment),
+            };
+            debug!("图片在 pdf 中的坐标 {:?}", position);
+
+            let clip = if pdf.need_clip() { Some(&cell) } else { None };
+
+            // 说明文字在图片可见部分的下方居中，图片超出单元格被裁剪时以单元格内的部分为准，
+            // 并且不低于单元格底部预留的一行
+            let caption = caption.map(|(text, size, width)| {
+                let mut left = position.x as f32;
+                let mut right = left + scaled.width as f32;
+                let mut bottom = position.y as f32;
+                if let Some(clip) = clip {
+                    left = left.max(clip.x);
+                    right = right.min(clip.x + clip.width);
+                    bottom = bottom.max(clip.y);
+                }
+                let bottom = bottom.clamp(cell.y, cell.y + cell.height);
+
+                let text_position =
+                    Position::from((left + (right - left - width) / 2.0, bottom - size));
+                debug!("图片说明 {:?} 的坐标 {:?}", text, text_position);
+
+                (text, text_position, size)
+            });
+
+            pdf.insert_image(page.id, &mut page.content, image, position, scaled, clip)
+                .map_err(|err| err.to_string())?;
+
+            if let Some((text, text_position, size)) = caption {
+                pdf.insert_text(
+                    page.id,
+                    &mut page.content,
+                    &text,
+                    text_position,
+                    size,
+                    [0, 0, 0],
+                )
+                .map_err(|err| err.to_string())?;
+            }
+        }
+
+        debug!("已向 pdf 插入图片：{:?}", item.path);
+    }
+
+    // 每页最多放置 cells 张图片
+    debug!("每页 {} 张图片，共 {} 页", cells, pages.len());
+
+    let count = pages.len() + imported.iter().map(|(_, ids)| ids.len()).sum::<usize>();
+    // 复制的页面没有页眉和页脚，不计入页码
+    let total = existing + pages.len();
+    let date = today();
+    let mut page_ids = Vec::with_capacity(count);
+    let mut imported = imported.into_iter().peekable();
+
+    for (i, mut page) in pages.into_iter().enumerate() {
+        // 复制的页面保持原样，不添加水印、页眉和页脚
+        while let Some((_, ids)) = imported.next_if(|(position, _)| *position == i) {
+            page_ids.extend(ids);
+        }
+
+        pdf.insert_watermark(&mut page, true)
+            .map_err(|err| err.to_string())?;
+
+        pdf.insert_page_texts(&mut page, offset + i + 1, total, &date)
+            .map_err(|err| err.to_string())?;
+
+        pdf.write_content(page.id, page.content)
+            .map_err(|err| err.to_string())?;
+
+        page_ids.push(page.id);
+    }
+
+    for (_, ids) in imported {
+        page_ids.extend(ids);
+    }
+
+    pdf.embed_fonts()?;
+
+    Ok(page_ids)
+}
+
+/// 把图片嵌入 pdf。
+///
+/// 使用并发添加图片，并发时需要保存图片顺序不能错乱。
+pub async fn embedd_images_to_new_pdf(
+    output: PathBuf,
+    images: Vec<models::Image>,
+    options: models::MergeOptions,
+) -> std::result::Result<(), String> {
+    let mut pdf = PDF::new(&options);
+
+    let page_ids = embed_images(&mut pdf, &images, &options, 0, 0).await?;
+    let total = page_ids.len();
+
+    let pages = dictionary! {
+        "Type" => "Pages",
+        "Kids" => page_ids.into_iter().map(Object::from).collect::<Vec<Object>>(),
+        "Count" => Object::Integer(total as i64),
+    };
+
+    // 必需插入 pages
+    pdf.insert_pages(pages);
+
+    // 必需有目录对象，即使目录不显示
+    pdf.create_catalog();
+
+    match pdf.save(output) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 把图片插入到已有的 pdf 中，index 为插入位置（从 0 开始），为空时追加到末尾。
+///
+/// 原文档的书签和文档信息保持不变，所以选项中的书签、页码标签和文档信息不会生效。
+pub async fn embedd_images_to_existing_pdf(
+    input: PathBuf,
+    output: PathBuf,
+    images: Vec<models::Image>,
+    options: models::MergeOptions,
+    index: Option<usize>,
+) -> std::result::Result<(), String> {
+    let mut pdf = PDF::load(&input, &options)?;
+
+    let existing = pdf.doc.get_pages().len();
+    let offset = index.unwrap_or(existing).min(existing);
+
+    let page_ids = embed_images(&mut pdf, &images, &options, offset, existing).await?;
+
+    pdf.insert_into_page_tree(&page_ids, offset)?;
+
+    match pdf.save(output) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 加载 pdf 文件用于页面操作，返回文档和页数。
+fn load_for_pages(input: &Path) -> std::result::Result<(PDF, usize), String> {
+    let pdf = PDF::load(input, &models::MergeOptions::default())?;
+    let total = pdf.doc.get_pages().len();
+
+    Ok((pdf, total))
+}
+
+/// 按页码范围拆分 pdf，ranges 为空时每页拆分为一个文件，返回生成的文件。
+///
+/// 文件保存在 output_dir 中，文件名为原文件名加上页码范围，如 contract_1-3.pdf。
+pub fn split_pdf(
+    input: PathBuf,
+    output_dir: PathBuf,
+    ranges: Option<String>,
+) -> std::result::Result<Vec<PathBuf>, String> {
+    let (pdf, total) = load_for_pages(&input)?;
+
+    create_dir(&output_dir)?;
+
+    let segments = match ranges {
+        Some(ranges) => parse_page_ranges(&ranges, total)?,
+        None => (1..=total).map(|number| vec![number]).collect(),
+    };
+
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "split".to_string());
+
+    let mut outputs = Vec::with_capacity(segments.len());
+
+    for numbers in segments {
+        let (first, last) = (numbers[0], numbers[numbers.len() - 1]);
+        let name = if first == last {
+            format!("{}_{}.pdf", stem, first)
+        } else {
+            format!("{}_{}-{}.pdf", stem, first, last)
+        };
+        let output = output_dir.join(name);
+
+        let mut part = PDF::new(&models::MergeOptions::default());
+        part.doc = pdf.copy_for_pages(&numbers);
+        part.pages_id = pdf.pages_id;
+        part.rebuild_page_tree(&numbers)?;
+        part.save(output.clone()).map_err(|e| e.to_string())?;
+
+        outputs.push(output);
+    }
+
+    info!("已把 {:?} 拆分为 {} 个文件", input, outputs.len());
+
+    Ok(outputs)
+}
+
+/// 提取页码范围中的页面保存为新文件，页面按范围中的顺序排列。
+pub fn extract_pages(
+    input: PathBuf,
+    output: PathBuf,
+    ranges: String,
+) -> std::result::Result<(), String> {
+    let (mut pdf, total) = load_for_pages(&input)?;
+
+    let numbers: Vec<usize> = parse_page_ranges(&ranges, total)?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    pdf.rebuild_page_tree(&numbers)?;
+    pdf.save(output).map_err(|e| e.to_string())
+}
+
+/// 删除页码范围中的页面，不能删除所有页面。
+pub fn delete_pages(
+    input: PathBuf,
+    output: PathBuf,
+    ranges: String,
+) -> std::result::Result<(), String> {
+    let (mut pdf, total) = load_for_pages(&input)?;
+
+    let deleted: BTreeSet<usize> = parse_page_ranges(&ranges, total)?
+        .into_iter()
+        .flatten()
+        .collect();
+    let numbers: Vec<usize> = (1..=total).filter(|n| !deleted.contains(n)).collect();
+
+    if numbers.is_empty() {
+        error!("不能删除所有页面：{}", ranges);
+        return Err("不能删除所有页面".to_string());
+    }
+
+    pdf.rebuild_page_tree(&numbers)?;
+    pdf.save(output).map_err(|e| e.to_string())
+}
+
+/// 按新的顺序排列页面，order 是由原页码（从 1 开始）组成的排列。
+pub fn reorder_pages(
+    input: PathBuf,
+    output: PathBuf,
+    order: Vec<usize>,
+) -> std::result::Result<(), String> {
+    let (mut pdf, total) = load_for_pages(&input)?;
+
+    let mut sorted = order.clone();
+    sorted.sort_unstable();
+    if sorted.into_iter().ne(1..=total) {
+        error!(
+            "页面顺序必需包含每一页且只包含一次：{:?}，共 {} 页",
+            order, total
+        );
+        return Err("页面顺序必需包含每一页且只包含一次".to_string());
+    }
+
+    pdf.rebuild_page_tree(&order)?;
+    pdf.save(output).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_page_ranges_segments() {
+        assert_eq!(
+            parse_page_ranges("1-3,5,8-", 10).unwrap(),
+            vec![vec![1, 2, 3], vec![5], vec![8, 9, 10]]
+        );
+        assert_eq!(
+            parse_page_ranges(" 2 , 4 - 5 ", 10).unwrap(),
+            vec![vec![2], vec![4, 5]]
+        );
+    }
+
+    #[test]
+    fn parse_page_ranges_open_start() {
+        assert_eq!(parse_page_ranges("-3", 10).unwrap(), vec![vec![1, 2, 3]]);
+        assert_eq!(parse_page_ranges("-", 2).unwrap(), vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn parse_page_ranges_invalid() {
+        // 反向范围
+        assert!(parse_page_ranges("3-1", 10).is_err());
+        // 页码从 1 开始
+        assert!(parse_page_ranges("0", 10).is_err());
+        assert!(parse_page_ranges("0-2", 10).is_err());
+        // 超出总页数
+        assert!(parse_page_ranges("11", 10).is_err());
+        assert!(parse_page_ranges("8-12", 10).is_err());
+        assert!(parse_page_ranges("99999999999999999999999", 10).is_err());
+        // 没有页码
+        assert!(parse_page_ranges("", 10).is_err());
+        assert!(parse_page_ranges(" , ", 10).is_err());
+        assert!(parse_page_ranges("a-b", 10).is_err());
     }
 }
@@ -177,6 +177,10 @@ const FloatButtons: React.FC<Props> = (props) => {
                 await invoke<null>('merge_images_to_pdf', {
                   output: filePath,
                   images: images.map((v) => ({ path: v.src })),
+                  options: {
+                    pageType: 'A4',
+                    orientation: 'Auto',
+                  },
                 })
 
                 // 使用系统默认阅读器打开 pdf
@@ -12,18 +12,144 @@ declare interface ImageItem {
 }
 
 declare interface Image {
+  // 图片或 pdf 文件的路径，pdf 文件的所有页面按原样复制
   path: string
-  // rotate: number
-  // scale: number
-  // width: number
-  // height: number
-  // position: [number, number]
+  // 顺时针旋转角度，90 的倍数
+  rotate?: number
+  // 尺寸，pdf 单元
+  size?: [number, number]
+  // 缩放比例，百分比
+  scale?: number
+  // 左下角坐标，pdf 单元
+  position?: [number, number]
+  // 图片下方的说明文字，优先于文件名
+  caption?: string
+}
+
+declare type PageType =
+  | 'Letter'
+  | 'Legal'
+  | 'Tabloid'
+  | 'A0' | 'A1' | 'A2' | 'A3' | 'A4' | 'A5' | 'A6'
+  | 'B0' | 'B1' | 'B2' | 'B3' | 'B4' | 'B5' | 'B6'
+  | 'JisB0' | 'JisB1' | 'JisB2' | 'JisB3' | 'JisB4' | 'JisB5' | 'JisB6'
+  | 'Image'
+
+declare type Orientation = 'Portrait' | 'Landscape' | 'Auto'
+
+declare type Alignment =
+  | 'TopLeft' | 'TopCenter' | 'TopRight'
+  | 'CenterLeft' | 'Center' | 'CenterRight'
+  | 'BottomLeft' | 'BottomCenter' | 'BottomRight'
+
+declare interface Margins {
+  top?: number
+  right?: number
+  bottom?: number
+  left?: number
+  unit?: 'Pt' | 'Mm'
+}
+
+// 每页的网格布局，图片按行从左上角开始排列
+declare interface Layout {
+  rows?: number
+  columns?: number
+  // 单元格之间的间距
+  gutter?: number
+  unit?: 'Pt' | 'Mm'
+}
+
+declare type FitMode = 'Contain' | 'Cover' | 'Stretch' | 'NeverUpscale' | 'ActualSize'
+
+// 图片下方的说明文字
+declare interface Captions {
+  // 没有指定说明文字时显示文件名
+  fileName?: boolean
+  fontSize?: number
+  // 中文等非 ASCII 文字使用的字体文件，为空时查找系统中文字体
+  font?: string
+}
+
+// 页眉或页脚，模板中可以使用 {page}、{total}、{filename} 和 {date}
+// 从 pdf 文件复制的页面不添加页眉和页脚，也不计入页码和总页数
+declare interface PageText {
+  template: string
+  align?: 'Left' | 'Center' | 'Right'
+  fontSize?: number
+  color?: [number, number, number]
+}
+
+// 水印，content 为 { Text: string } 或 { Image: string }（图片路径）
+declare interface Watermark {
+  content: { Text: string } | { Image: string }
+  fontSize?: number
+  color?: [number, number, number]
+  // 图片水印的宽度，pdf 单元
+  width?: number
+  // 逆时针旋转角度
+  rotate?: number
+  // 不透明度，0～1
+  opacity?: number
+  tiled?: boolean
+  // 平铺间距，pdf 单元
+  gap?: number
+  // 是否绘制在图片上方
+  above?: boolean
+}
+
+// 文档信息
+declare interface Metadata {
+  title?: string
+  author?: string
+  subject?: string
+  // 多个关键词之间用逗号分隔
+  keywords?: string
+}
+
+// 动图的处理方式：只使用第一帧、每一帧各占一页、所有帧排列在同一页
+declare type AnimationMode = 'First' | 'All' | 'ContactSheet'
+
+// 书签的生成方式：不生成、每张图片一个书签、按图片所在目录分组
+declare type OutlineMode = 'None' | 'Images' | 'Folders'
+
+// 页码标签，从 start 页（从 1 开始）到下一段之前的页面使用此标签
+declare interface PageLabel {
+  start?: number
+  style?: 'Decimal' | 'UpperRoman' | 'LowerRoman' | 'UpperLetters' | 'LowerLetters' | 'None'
+  // 页码前缀，如 "A-"
+  prefix?: string
+  // 第一页的编号
+  first?: number
+}
+
+declare interface MergeOptions {
+  pageType?: PageType
+  orientation?: Orientation
+  margins?: Margins
+  alignment?: Alignment
+  fitMode?: FitMode
+  layout?: Layout
+  dpi?: number
+  // 透明图片的背景色，为空时保留透明通道
+  background?: [number, number, number]
+  // 黑白图片使用 CCITT G4 编码
+  ccitt?: boolean
+  animation?: AnimationMode
+  captions?: Captions
+  header?: PageText
+  footer?: PageText
+  watermark?: Watermark
+  metadata?: Metadata
+  outline?: OutlineMode
+  pageLabels?: PageLabel[]
 }
 
 declare interface Thumbnail {
   src: string
   base64: string
   name: string
+  // 图片的页数，多页 tiff 会生成多个 pdf 页面，pdf 文件为其页数
+  frames: number
 }
 
 type KeyOfType<T, U> = {